@@ -0,0 +1,208 @@
+// The untyped AST: identifiers are plain names and expressions are not sorted by type yet. It
+// goes through the checker in `semantics` to become a `typed_ast::Function`
+
+use num_bigint::BigUint;
+use std::fmt;
+use typed_ast::Type;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Variable {
+    pub id: String,
+    pub _type: Type,
+}
+
+impl Variable {
+    pub fn new<S: Into<String>>(id: S, _type: Type) -> Self {
+        Variable {
+            id: id.into(),
+            _type,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Parameter {
+    pub id: Variable,
+    pub private: bool,
+}
+
+impl Parameter {
+    pub fn new(id: Variable, private: bool) -> Self {
+        Parameter { id, private }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expression {
+    // checked against the modulus of the field the program is compiled for
+    FieldConstant(BigUint),
+    BooleanConstant(bool),
+    // the value and the bit width
+    UintConstant(u64, usize),
+    Identifier(String),
+    Add(Box<Expression>, Box<Expression>),
+    Sub(Box<Expression>, Box<Expression>),
+    Mult(Box<Expression>, Box<Expression>),
+    Div(Box<Expression>, Box<Expression>),
+    // the exponent must be a constant
+    Pow(Box<Expression>, Box<Expression>),
+    // the boolean operators also apply bit by bit to integers
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Xor(Box<Expression>, Box<Expression>),
+    // the number of bits to shift by must be a constant
+    LeftShift(Box<Expression>, Box<Expression>),
+    RightShift(Box<Expression>, Box<Expression>),
+    Eq(Box<Expression>, Box<Expression>),
+    Neq(Box<Expression>, Box<Expression>),
+    Lt(Box<Expression>, Box<Expression>),
+    Le(Box<Expression>, Box<Expression>),
+    Gt(Box<Expression>, Box<Expression>),
+    Ge(Box<Expression>, Box<Expression>),
+    ArrayValue(Vec<Expression>),
+    StructValue(Vec<(String, Expression)>),
+    Member(Box<Expression>, String),
+    TupleValue(Vec<Expression>),
+    // `t.0`
+    Element(Box<Expression>, usize),
+    // `Op::Add(1)`, the type being the enum
+    EnumValue(Type, String, Box<Expression>),
+    // `match e { Add(a) => a, Swap(b) => b[0] }`, each arm giving the variant and the name its
+    // payload is bound to
+    Match(Box<Expression>, Vec<(String, String, Expression)>),
+    SomeValue(Box<Expression>),
+    // the type of the value which is absent
+    NoneValue(Type),
+    IsSome(Box<Expression>),
+    // `o.unwrap_or(d)`, the value of `o` if it is present, `d` otherwise
+    UnwrapOr(Box<Expression>, Box<Expression>),
+    Select(Box<Expression>, Box<Expression>),
+    IfElse(Box<Expression>, Box<Expression>, Box<Expression>),
+    FunctionCall(String, Vec<Expression>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Statement {
+    Definition(Variable, Expression),
+    // `a, b = foo()`, each variable taking the matching element of a tuple
+    MultipleDefinition(Vec<Variable>, Expression),
+    Assignment(String, Expression),
+    Return(Vec<Expression>),
+    // `for i in start..end`, the bounds being constants
+    For(Variable, Size, Size, Vec<Statement>),
+    // `assert_eq(a, b)`, the two sides having the same type
+    AssertEq(Expression, Expression),
+}
+
+// A size known when checking, either as it is or once the generic function it appears in is
+// instantiated
+#[derive(Debug, PartialEq, Clone)]
+pub enum Size {
+    Constant(usize),
+    Generic(String),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function {
+    pub id: String,
+    // the sizes the function is generic over, as `N` in `def sum<N>(field[N] xs)`
+    pub generics: Vec<String>,
+    pub arguments: Vec<Parameter>,
+    pub returns: Vec<Type>,
+    pub statements: Vec<Statement>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Program {
+    pub functions: Vec<Function>,
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expression::FieldConstant(ref v) => write!(f, "{}", v),
+            Expression::BooleanConstant(ref v) => write!(f, "{}", v),
+            Expression::UintConstant(v, bitwidth) => write!(f, "{}_u{}", v, bitwidth),
+            Expression::Identifier(ref id) => write!(f, "{}", id),
+            Expression::Add(ref e1, ref e2) => write!(f, "{} + {}", e1, e2),
+            Expression::Sub(ref e1, ref e2) => write!(f, "{} - {}", e1, e2),
+            Expression::Mult(ref e1, ref e2) => write!(f, "{} * {}", e1, e2),
+            Expression::Div(ref e1, ref e2) => write!(f, "{} / {}", e1, e2),
+            Expression::Pow(ref e1, ref e2) => write!(f, "{}**{}", e1, e2),
+            Expression::And(ref e1, ref e2) => write!(f, "{} ^ {}", e1, e2),
+            Expression::Or(ref e1, ref e2) => write!(f, "{} v {}", e1, e2),
+            Expression::Not(ref e) => write!(f, "!{}", e),
+            Expression::Xor(ref e1, ref e2) => write!(f, "{} xor {}", e1, e2),
+            Expression::LeftShift(ref e1, ref e2) => write!(f, "{} << {}", e1, e2),
+            Expression::RightShift(ref e1, ref e2) => write!(f, "{} >> {}", e1, e2),
+            Expression::Eq(ref e1, ref e2) => write!(f, "{} == {}", e1, e2),
+            Expression::Neq(ref e1, ref e2) => write!(f, "{} != {}", e1, e2),
+            Expression::Lt(ref e1, ref e2) => write!(f, "{} < {}", e1, e2),
+            Expression::Le(ref e1, ref e2) => write!(f, "{} <= {}", e1, e2),
+            Expression::Gt(ref e1, ref e2) => write!(f, "{} > {}", e1, e2),
+            Expression::Ge(ref e1, ref e2) => write!(f, "{} >= {}", e1, e2),
+            Expression::ArrayValue(ref values) => write!(
+                f,
+                "[{}]",
+                values
+                    .iter()
+                    .map(|e| format!("{}", e))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Expression::StructValue(ref values) => write!(
+                f,
+                "{{{}}}",
+                values
+                    .iter()
+                    .map(|(id, e)| format!("{}: {}", id, e))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Expression::Member(ref s, ref id) => write!(f, "{}.{}", s, id),
+            Expression::TupleValue(ref values) => match values.len() {
+                1 => write!(f, "({},)", values[0]),
+                _ => write!(
+                    f,
+                    "({})",
+                    values
+                        .iter()
+                        .map(|e| format!("{}", e))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            },
+            Expression::Element(ref t, index) => write!(f, "{}.{}", t, index),
+            Expression::EnumValue(ref ty, ref id, ref payload) => {
+                write!(f, "{}::{}({})", ty, id, payload)
+            }
+            Expression::Match(ref e, ref arms) => write!(
+                f,
+                "match {} {{ {} }}",
+                e,
+                arms.iter()
+                    .map(|(variant, binding, e)| format!("{}({}) => {}", variant, binding, e))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Expression::SomeValue(ref e) => write!(f, "Some({})", e),
+            Expression::NoneValue(_) => write!(f, "None"),
+            Expression::IsSome(ref e) => write!(f, "{}.is_some()", e),
+            Expression::UnwrapOr(ref o, ref d) => write!(f, "{}.unwrap_or({})", o, d),
+            Expression::Select(ref a, ref i) => write!(f, "{}[{}]", a, i),
+            Expression::IfElse(ref c, ref a, ref b) => {
+                write!(f, "if {} then {} else {}", c, a, b)
+            }
+            Expression::FunctionCall(ref id, ref args) => write!(
+                f,
+                "{}({})",
+                id,
+                args.iter()
+                    .map(|e| format!("{}", e))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
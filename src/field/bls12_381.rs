@@ -0,0 +1,5 @@
+// the scalar field of the BLS12-381 curve
+prime_field!(
+    Bls12Field,
+    "52435875175126190479447740508185965837690552500527637822603658699938581184513"
+);
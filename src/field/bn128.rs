@@ -0,0 +1,5 @@
+// the scalar field of the BN254 curve, also known as alt_bn128
+prime_field!(
+    Bn128Field,
+    "21888242871839275222246405745257275088548364400416034343698204186575808495617"
+);
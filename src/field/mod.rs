@@ -0,0 +1,232 @@
+// The prime fields constraints can be expressed in. Values are kept reduced, in `0..p`. The field
+// is a type parameter of the typed AST and the flattener, picked once per compilation
+
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+use std::fmt::{Debug, Display};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+pub trait Field:
+    'static
+    + From<usize>
+    + Zero
+    + One
+    + Clone
+    + PartialEq
+    + Debug
+    + Display
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    fn modulus() -> BigUint;
+
+    // the number of bits the largest element takes
+    fn get_required_bits() -> usize {
+        (Self::modulus() - 1u32).bits()
+    }
+
+    fn to_biguint(&self) -> BigUint;
+
+    // the element `value` stands for, if it is smaller than the modulus
+    fn try_from_biguint(value: BigUint) -> Option<Self>;
+
+    // the multiplicative inverse, if there is one
+    fn inverse_mul(&self) -> Option<Self>;
+
+    // square and multiply
+    fn pow(&self, exponent: usize) -> Self {
+        let mut result = Self::one();
+        let mut square = self.clone();
+        let mut exponent = exponent;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result * square.clone();
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                square = square.clone() * square;
+            }
+        }
+        result
+    }
+}
+
+// a field of integers modulo the prime written in decimal as `$modulus`
+macro_rules! prime_field {
+    ($name:ident, $modulus:expr) => {
+        use field::Field;
+        use num_bigint::BigUint;
+        use num_traits::{Num, One, Zero};
+        use std::fmt;
+        use std::ops::{Add, Div, Mul, Neg, Sub};
+
+        lazy_static! {
+            static ref P: BigUint = BigUint::from_str_radix($modulus, 10).unwrap();
+        }
+
+        #[derive(Debug, PartialEq, Clone)]
+        pub struct $name {
+            value: BigUint,
+        }
+
+        impl $name {
+            fn reduce(value: BigUint) -> Self {
+                $name { value: value % &*P }
+            }
+        }
+
+        impl Field for $name {
+            fn modulus() -> BigUint {
+                P.clone()
+            }
+
+            fn to_biguint(&self) -> BigUint {
+                self.value.clone()
+            }
+
+            fn try_from_biguint(value: BigUint) -> Option<Self> {
+                if value < *P {
+                    Some($name { value })
+                } else {
+                    None
+                }
+            }
+
+            // `a^(p - 2)` is the inverse of `a` by Fermat's little theorem
+            fn inverse_mul(&self) -> Option<Self> {
+                if self.value.is_zero() {
+                    return None;
+                }
+                Some($name {
+                    value: self.value.modpow(&(&*P - 2u32), &*P),
+                })
+            }
+        }
+
+        impl From<usize> for $name {
+            fn from(value: usize) -> Self {
+                Self::reduce(BigUint::from(value))
+            }
+        }
+
+        impl Zero for $name {
+            fn zero() -> Self {
+                $name {
+                    value: BigUint::zero(),
+                }
+            }
+
+            fn is_zero(&self) -> bool {
+                self.value.is_zero()
+            }
+        }
+
+        impl One for $name {
+            fn one() -> Self {
+                $name {
+                    value: BigUint::one(),
+                }
+            }
+        }
+
+        impl Add for $name {
+            type Output = $name;
+
+            fn add(self, other: $name) -> $name {
+                Self::reduce(self.value + other.value)
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $name;
+
+            fn sub(self, other: $name) -> $name {
+                Self::reduce(self.value + &*P - other.value)
+            }
+        }
+
+        impl Mul for $name {
+            type Output = $name;
+
+            fn mul(self, other: $name) -> $name {
+                Self::reduce(self.value * other.value)
+            }
+        }
+
+        impl Div for $name {
+            type Output = $name;
+
+            fn div(self, other: $name) -> $name {
+                match other.inverse_mul() {
+                    Some(inverse) => self * inverse,
+                    None => panic!("division by zero"),
+                }
+            }
+        }
+
+        impl Neg for $name {
+            type Output = $name;
+
+            fn neg(self) -> $name {
+                Self::zero() - self
+            }
+        }
+
+        // elements past the middle of the field are shown as negative numbers, so that `p - 1`
+        // reads `-1`
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                if self.value > &*P >> 1 {
+                    write!(f, "-{}", &*P - &self.value)
+                } else {
+                    write!(f, "{}", self.value)
+                }
+            }
+        }
+    };
+}
+
+mod bls12_381;
+mod bn128;
+
+pub use self::bls12_381::*;
+pub use self::bn128::*;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn arithmetic<T: Field>() {
+        let minus_one = T::zero() - T::one();
+
+        assert_eq!(minus_one.to_string(), "-1");
+        assert_eq!(minus_one.clone() + T::one(), T::zero());
+        assert_eq!(minus_one.clone() * minus_one.clone(), T::one());
+        assert_eq!(T::from(3) / T::from(4) * T::from(4), T::from(3));
+        assert_eq!(T::zero().inverse_mul(), None);
+        assert_eq!(T::from(2).pow(10), T::from(1024));
+        assert_eq!(T::from(7).pow(0), T::one());
+        // an exponent far beyond what a linear loop could reach
+        assert_eq!(T::from(3).pow(1 << 40), T::from(3).pow(1 << 20).pow(1 << 20));
+        assert_eq!(T::try_from_biguint(T::modulus()), None);
+        assert_eq!(
+            T::try_from_biguint(T::modulus() - 1u32),
+            Some(minus_one.clone())
+        );
+    }
+
+    #[test]
+    fn bn128() {
+        arithmetic::<Bn128Field>();
+        assert_eq!(Bn128Field::get_required_bits(), 254);
+    }
+
+    #[test]
+    fn bls12_381() {
+        arithmetic::<Bls12Field>();
+        assert_eq!(Bls12Field::get_required_bits(), 255);
+    }
+}
@@ -1,23 +1,145 @@
+use field::Field;
 use std::fmt;
+use std::ops::{Add, Sub};
 
 #[derive(Debug, PartialEq, Clone)]
-pub struct LinComb(pub Vec<(usize, FlatVariable)>);
+pub struct LinComb<T: Field>(pub Vec<(T, FlatVariable)>);
+
+impl<T: Field> LinComb<T> {
+    pub fn constant(value: T) -> LinComb<T> {
+        LinComb(vec![(value, FlatVariable::one())])
+    }
+
+    pub fn scale(self, by: T) -> LinComb<T> {
+        LinComb(
+            self.0
+                .into_iter()
+                .map(|(mult, var)| (mult * by.clone(), var))
+                .collect(),
+        )
+    }
+
+    // the value of this linear combination if it only involves `~one`
+    pub fn as_constant(&self) -> Option<T> {
+        self.0.iter().try_fold(T::zero(), |acc, (mult, var)| {
+            if *var == FlatVariable::one() {
+                Some(acc + mult.clone())
+            } else if mult.is_zero() {
+                Some(acc)
+            } else {
+                None
+            }
+        })
+    }
+}
+
+// terms on the same variable are merged
+impl<T: Field> Add for LinComb<T> {
+    type Output = LinComb<T>;
+
+    fn add(self, other: LinComb<T>) -> LinComb<T> {
+        let mut terms = self.0;
+        for (mult, var) in other.0 {
+            match terms.iter().position(|(_, v)| *v == var) {
+                Some(index) => terms[index].0 = terms[index].0.clone() + mult,
+                None => terms.push((mult, var)),
+            }
+        }
+        LinComb(terms)
+    }
+}
+
+impl<T: Field> Sub for LinComb<T> {
+    type Output = LinComb<T>;
+
+    fn sub(self, other: LinComb<T>) -> LinComb<T> {
+        self + other.scale(-T::one())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Helper {
+    // the outputs are the inputs
+    Identity,
+    // the single output is the product of the two inputs
+    Product,
+    // the single output is the inverse of the input, or zero if the input is zero
+    Inverse,
+    // the outputs are the bits of the input, least significant first
+    Bits,
+    // the outputs are a one-hot encoding of the input, which is smaller than their count
+    Selector,
+}
 
 #[derive(Debug, PartialEq)]
-pub enum FlatStatement {
-    Directive(Vec<FlatVariable>),
-    Assertion(LinComb, LinComb, LinComb),
+pub enum FlatStatement<T: Field> {
+    Directive(Vec<FlatVariable>, Helper, Vec<LinComb<T>>),
+    Assertion(LinComb<T>, LinComb<T>, LinComb<T>),
     Return(Vec<FlatVariable>),
 }
 
 #[derive(Debug, PartialEq)]
-pub struct FlatFunction {
-    statements: Vec<FlatStatement>,
+pub struct FlatFunction<T: Field> {
+    public_inputs: Vec<FlatVariable>,
+    private_inputs: Vec<FlatVariable>,
+    outputs: Vec<FlatVariable>,
+    statements: Vec<FlatStatement<T>>,
 }
 
-impl FlatFunction {
-    pub fn with_statements(statements: Vec<FlatStatement>) -> Self {
-        FlatFunction { statements }
+impl<T: Field> FlatFunction<T> {
+    pub fn new(
+        public_inputs: Vec<FlatVariable>,
+        private_inputs: Vec<FlatVariable>,
+        outputs: Vec<FlatVariable>,
+        statements: Vec<FlatStatement<T>>,
+    ) -> Self {
+        FlatFunction {
+            public_inputs,
+            private_inputs,
+            outputs,
+            statements,
+        }
+    }
+
+    pub fn with_statements(statements: Vec<FlatStatement<T>>) -> Self {
+        Self::new(vec![], vec![], vec![], statements)
+    }
+
+    pub fn public_inputs(&self) -> &Vec<FlatVariable> {
+        &self.public_inputs
+    }
+
+    pub fn private_inputs(&self) -> &Vec<FlatVariable> {
+        &self.private_inputs
+    }
+
+    pub fn outputs(&self) -> &Vec<FlatVariable> {
+        &self.outputs
+    }
+
+    pub fn statements(&self) -> &Vec<FlatStatement<T>> {
+        &self.statements
+    }
+}
+
+// the flattened functions of a program, each one standing on its own, `main` being the entry
+// point
+#[derive(Debug, PartialEq)]
+pub struct FlatProg<T: Field> {
+    functions: Vec<(String, FlatFunction<T>)>,
+}
+
+impl<T: Field> FlatProg<T> {
+    pub fn with_functions(functions: Vec<(String, FlatFunction<T>)>) -> Self {
+        FlatProg { functions }
+    }
+
+    pub fn function(&self, id: &str) -> Option<&FlatFunction<T>> {
+        self.functions.iter().find(|(i, _)| i == id).map(|(_, f)| f)
+    }
+
+    pub fn main(&self) -> Option<&FlatFunction<T>> {
+        self.function("main")
     }
 }
 
@@ -32,9 +154,20 @@ impl FlatVariable {
     pub fn one() -> Self {
         Self::with_name("~one")
     }
+
+    // the variable the slot `index` of the returned values is bound to
+    pub fn output(index: usize) -> Self {
+        Self::with_name(format!("~out_{}", index))
+    }
+}
+
+impl<T: Field> From<FlatVariable> for LinComb<T> {
+    fn from(v: FlatVariable) -> LinComb<T> {
+        LinComb(vec![(T::one(), v)])
+    }
 }
 
-impl fmt::Display for FlatFunction {
+impl<T: Field> fmt::Display for FlatFunction<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
@@ -48,13 +181,27 @@ impl fmt::Display for FlatFunction {
     }
 }
 
+impl<T: Field> fmt::Display for FlatProg<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.functions
+                .iter()
+                .map(|(id, function)| format!("def {}:\n{}", id, function))
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        )
+    }
+}
+
 impl fmt::Display for FlatVariable {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.0)
     }
 }
 
-impl fmt::Display for LinComb {
+impl<T: Field> fmt::Display for LinComb<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
@@ -68,13 +215,31 @@ impl fmt::Display for LinComb {
     }
 }
 
-impl fmt::Display for FlatStatement {
+impl fmt::Display for Helper {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            FlatStatement::Directive(ref vars) => write!(
+            Helper::Identity => write!(f, "Identity"),
+            Helper::Product => write!(f, "Product"),
+            Helper::Inverse => write!(f, "Inverse"),
+            Helper::Bits => write!(f, "Bits"),
+            Helper::Selector => write!(f, "Selector"),
+        }
+    }
+}
+
+impl<T: Field> fmt::Display for FlatStatement<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FlatStatement::Directive(ref vars, ref helper, ref inputs) => write!(
                 f,
-                "# {} := TODO_DIRECTIVE(TODO_INPUTS)",
+                "# {} := {}({})",
                 vars.iter()
+                    .map(|e| format!("{}", e))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                helper,
+                inputs
+                    .iter()
                     .map(|e| format!("{}", e))
                     .collect::<Vec<_>>()
                     .join(", ")
@@ -1,6 +1,14 @@
 #![feature(box_patterns, box_syntax)]
 
+#[macro_use]
+extern crate lazy_static;
+extern crate num_bigint;
+extern crate num_traits;
+
+mod absy;
+mod field;
 mod flat_ast;
+mod semantics;
 mod typed_ast;
 
 pub fn main() {}
@@ -0,0 +1,3266 @@
+// The checker: resolves the identifiers of an untyped function, infers the type of every
+// expression and checks them against each other, producing the nodes of the typed AST
+
+use absy;
+use field::Field;
+use num_bigint::BigUint;
+use num_traits::{pow, ToPrimitive};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use typed_ast::*;
+
+#[derive(Debug, PartialEq)]
+pub struct Error {
+    message: String,
+}
+
+impl Error {
+    fn new<S: Into<String>>(message: S) -> Self {
+        Error {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// A checked expression, sorted by type
+enum TypedExpression<T: Field> {
+    FieldElement(FieldElement<T>),
+    Boolean(Boolean<T>),
+    Uint(Uint<T>),
+    Array(ArrayExpression<T>),
+    Structure(Structure<T>),
+    Tuple(Tuple<T>),
+    Enumeration(Enumeration<T>),
+    Optional(Optional<T>),
+}
+
+// A checked array expression, before it becomes the `Array<T, V>` its user needs
+enum ArrayExpression<T: Field> {
+    Value(Vec<TypedExpression<T>>),
+    Identifier(Variable),
+    Member(Structure<T>, String),
+    Element(Tuple<T>, usize),
+    Select(Box<ArrayExpression<T>>, FieldElement<T>),
+    IfElse(Boolean<T>, Box<ArrayExpression<T>>, Box<ArrayExpression<T>>),
+    Match(Enumeration<T>, Vec<MatchArm<ArrayExpression<T>>>),
+    UnwrapOr(Optional<T>, Box<ArrayExpression<T>>),
+    FunctionCall(Type, String, Vec<Box<Expression<T>>>),
+}
+
+// The element types an `ArrayExpression` can be turned into. The checker only asks for an
+// element type once it knows it is the right one
+trait FromTyped<T: Field>: Expression<T> + Sized {
+    fn from_typed(e: TypedExpression<T>) -> Self;
+}
+
+impl<T: Field> FromTyped<T> for FieldElement<T> {
+    fn from_typed(e: TypedExpression<T>) -> Self {
+        match e {
+            TypedExpression::FieldElement(e) => e,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<T: Field> FromTyped<T> for Boolean<T> {
+    fn from_typed(e: TypedExpression<T>) -> Self {
+        match e {
+            TypedExpression::Boolean(e) => e,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<T: Field> FromTyped<T> for Uint<T> {
+    fn from_typed(e: TypedExpression<T>) -> Self {
+        match e {
+            TypedExpression::Uint(e) => e,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<T: Field> FromTyped<T> for Structure<T> {
+    fn from_typed(e: TypedExpression<T>) -> Self {
+        match e {
+            TypedExpression::Structure(e) => e,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<T: Field> FromTyped<T> for Tuple<T> {
+    fn from_typed(e: TypedExpression<T>) -> Self {
+        match e {
+            TypedExpression::Tuple(e) => e,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<T: Field> FromTyped<T> for Enumeration<T> {
+    fn from_typed(e: TypedExpression<T>) -> Self {
+        match e {
+            TypedExpression::Enumeration(e) => e,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<T: Field> FromTyped<T> for Optional<T> {
+    fn from_typed(e: TypedExpression<T>) -> Self {
+        match e {
+            TypedExpression::Optional(e) => e,
+            _ => unreachable!(),
+        }
+    }
+}
+
+// arrays of arrays can be nested arbitrarily deep, so their elements are left untyped
+impl<T: Field> FromTyped<T> for Box<Expression<T>> {
+    fn from_typed(e: TypedExpression<T>) -> Self {
+        e.into_boxed()
+    }
+}
+
+impl<T: Field> TypedExpression<T> {
+    fn identifier(v: Variable) -> Self {
+        match v.get_type() {
+            Type::FieldElement => TypedExpression::FieldElement(FieldElement::Identifier(v)),
+            Type::Boolean => TypedExpression::Boolean(Boolean::Identifier(v)),
+            Type::Uint(..) => TypedExpression::Uint(Uint::Identifier(v)),
+            Type::Array(..) => TypedExpression::Array(ArrayExpression::Identifier(v)),
+            Type::Struct(..) => TypedExpression::Structure(Structure::Identifier(v)),
+            Type::Tuple(..) => TypedExpression::Tuple(Tuple::Identifier(v)),
+            Type::Enum(..) => TypedExpression::Enumeration(Enumeration::Identifier(v)),
+            Type::Option(..) => TypedExpression::Optional(Optional::Identifier(v)),
+            Type::GenericArray(..) => unreachable!(),
+        }
+    }
+
+    fn member(s: Structure<T>, id: String, ty: Type) -> Self {
+        match ty {
+            Type::FieldElement => TypedExpression::FieldElement(FieldElement::Member(box s, id)),
+            Type::Boolean => TypedExpression::Boolean(Boolean::Member(box s, id)),
+            Type::Uint(..) => TypedExpression::Uint(Uint::Member(box s, id)),
+            Type::Array(..) => TypedExpression::Array(ArrayExpression::Member(s, id)),
+            Type::Struct(..) => TypedExpression::Structure(Structure::Member(box s, id)),
+            Type::Tuple(..) => TypedExpression::Tuple(Tuple::Member(box s, id)),
+            Type::Enum(..) => TypedExpression::Enumeration(Enumeration::Member(box s, id)),
+            Type::Option(..) => TypedExpression::Optional(Optional::Member(box s, id)),
+            Type::GenericArray(..) => unreachable!(),
+        }
+    }
+
+    fn element(t: Tuple<T>, index: usize, ty: Type) -> Self {
+        match ty {
+            Type::FieldElement => {
+                TypedExpression::FieldElement(FieldElement::Element(box t, index))
+            }
+            Type::Boolean => TypedExpression::Boolean(Boolean::Element(box t, index)),
+            Type::Uint(..) => TypedExpression::Uint(Uint::Element(box t, index)),
+            Type::Array(..) => TypedExpression::Array(ArrayExpression::Element(t, index)),
+            Type::Struct(..) => TypedExpression::Structure(Structure::Element(box t, index)),
+            Type::Tuple(..) => TypedExpression::Tuple(Tuple::Element(box t, index)),
+            Type::Enum(..) => TypedExpression::Enumeration(Enumeration::Element(box t, index)),
+            Type::Option(..) => TypedExpression::Optional(Optional::Element(box t, index)),
+            Type::GenericArray(..) => unreachable!(),
+        }
+    }
+
+    // the arms all evaluate to `self`'s type, which is `ty`
+    fn match_arms(e: Enumeration<T>, arms: Vec<MatchArm<TypedExpression<T>>>, ty: Type) -> Self {
+        match ty {
+            Type::FieldElement => {
+                TypedExpression::FieldElement(FieldElement::Match(box e, convert_arms(arms)))
+            }
+            Type::Boolean => TypedExpression::Boolean(Boolean::Match(box e, convert_arms(arms))),
+            Type::Uint(..) => TypedExpression::Uint(Uint::Match(box e, convert_arms(arms))),
+            Type::Array(..) => TypedExpression::Array(ArrayExpression::Match(
+                e,
+                arms.into_iter()
+                    .map(|arm| MatchArm {
+                        variant: arm.variant,
+                        binding: arm.binding,
+                        expression: match *arm.expression {
+                            TypedExpression::Array(a) => box a,
+                            _ => unreachable!(),
+                        },
+                    }).collect(),
+            )),
+            Type::Struct(..) => {
+                TypedExpression::Structure(Structure::Match(box e, convert_arms(arms)))
+            }
+            Type::Tuple(..) => TypedExpression::Tuple(Tuple::Match(box e, convert_arms(arms))),
+            Type::Enum(..) => {
+                TypedExpression::Enumeration(Enumeration::Match(box e, convert_arms(arms)))
+            }
+            Type::Option(..) => {
+                TypedExpression::Optional(Optional::Match(box e, convert_arms(arms)))
+            }
+            Type::GenericArray(..) => unreachable!(),
+        }
+    }
+
+    // `default` is of the type of the value of `o`
+    fn unwrap_or(o: Optional<T>, default: Self) -> Self {
+        match default {
+            TypedExpression::FieldElement(d) => {
+                TypedExpression::FieldElement(FieldElement::UnwrapOr(box o, box d))
+            }
+            TypedExpression::Boolean(d) => {
+                TypedExpression::Boolean(Boolean::UnwrapOr(box o, box d))
+            }
+            TypedExpression::Uint(d) => TypedExpression::Uint(Uint::UnwrapOr(box o, box d)),
+            TypedExpression::Array(d) => {
+                TypedExpression::Array(ArrayExpression::UnwrapOr(o, box d))
+            }
+            TypedExpression::Structure(d) => {
+                TypedExpression::Structure(Structure::UnwrapOr(box o, box d))
+            }
+            TypedExpression::Tuple(d) => TypedExpression::Tuple(Tuple::UnwrapOr(box o, box d)),
+            TypedExpression::Enumeration(d) => {
+                TypedExpression::Enumeration(Enumeration::UnwrapOr(box o, box d))
+            }
+            TypedExpression::Optional(d) => {
+                TypedExpression::Optional(Optional::UnwrapOr(box o, box d))
+            }
+        }
+    }
+
+    fn get_type(&self) -> Type {
+        match *self {
+            TypedExpression::FieldElement(ref e) => e.get_type(),
+            TypedExpression::Boolean(ref e) => e.get_type(),
+            TypedExpression::Uint(ref e) => e.get_type(),
+            TypedExpression::Array(ref e) => e.get_type(),
+            TypedExpression::Structure(ref e) => e.get_type(),
+            TypedExpression::Tuple(ref e) => e.get_type(),
+            TypedExpression::Enumeration(ref e) => e.get_type(),
+            TypedExpression::Optional(ref e) => e.get_type(),
+        }
+    }
+
+    fn into_boxed(self) -> Box<Expression<T>> {
+        match self {
+            TypedExpression::FieldElement(e) => box e,
+            TypedExpression::Boolean(e) => box e,
+            TypedExpression::Uint(e) => box e,
+            TypedExpression::Array(e) => e.into_boxed(),
+            TypedExpression::Structure(e) => box e,
+            TypedExpression::Tuple(e) => box e,
+            TypedExpression::Enumeration(e) => box e,
+            TypedExpression::Optional(e) => box e,
+        }
+    }
+}
+
+impl<T: Field> ArrayExpression<T> {
+    fn get_type(&self) -> Type {
+        match *self {
+            ArrayExpression::Value(ref v) => Type::Array(box v[0].get_type(), v.len()),
+            ArrayExpression::Identifier(ref v) => v.get_type(),
+            ArrayExpression::Member(ref s, ref id) => s.member_type(id),
+            ArrayExpression::Element(ref t, index) => t.element_type(index),
+            ArrayExpression::Select(ref a, _) => match a.get_type() {
+                Type::Array(inner, _) => *inner,
+                _ => unreachable!(),
+            },
+            ArrayExpression::IfElse(_, ref a, _) => a.get_type(),
+            ArrayExpression::Match(_, ref arms) => arms[0].expression.get_type(),
+            ArrayExpression::UnwrapOr(_, ref d) => d.get_type(),
+            ArrayExpression::FunctionCall(ref ty, ..) => ty.clone(),
+        }
+    }
+
+    fn into_array<V: FromTyped<T>>(self) -> Array<T, V> {
+        match self {
+            ArrayExpression::Value(v) => Array::Value(v.into_iter().map(V::from_typed).collect()),
+            ArrayExpression::Identifier(v) => Array::Identifier(v),
+            ArrayExpression::Member(s, id) => Array::Member(box s, id),
+            ArrayExpression::Element(t, index) => Array::Element(box t, index),
+            ArrayExpression::Select(a, i) => Array::Select(a.into_boxed(), box i),
+            ArrayExpression::IfElse(c, a, b) => {
+                Array::IfElse(box c, box a.into_array(), box b.into_array())
+            }
+            ArrayExpression::Match(e, arms) => Array::Match(
+                box e,
+                arms.into_iter()
+                    .map(|arm| MatchArm {
+                        variant: arm.variant,
+                        binding: arm.binding,
+                        expression: box arm.expression.into_array(),
+                    }).collect(),
+            ),
+            ArrayExpression::UnwrapOr(o, d) => Array::UnwrapOr(box o, box d.into_array()),
+            ArrayExpression::FunctionCall(ty, id, args) => Array::FunctionCall(ty, id, args),
+        }
+    }
+
+    fn into_boxed(self) -> Box<Expression<T>> {
+        let inner = match self.get_type() {
+            Type::Array(inner, _) => *inner,
+            _ => unreachable!(),
+        };
+
+        match inner {
+            Type::FieldElement => box self.into_array::<FieldElement<T>>(),
+            Type::Boolean => box self.into_array::<Boolean<T>>(),
+            Type::Uint(..) => box self.into_array::<Uint<T>>(),
+            Type::Struct(..) => box self.into_array::<Structure<T>>(),
+            Type::Tuple(..) => box self.into_array::<Tuple<T>>(),
+            Type::Enum(..) => box self.into_array::<Enumeration<T>>(),
+            Type::Option(..) => box self.into_array::<Optional<T>>(),
+            Type::Array(..) => box self.into_array::<Box<Expression<T>>>(),
+            Type::GenericArray(..) => unreachable!(),
+        }
+    }
+}
+
+fn convert_arms<T: Field, V: FromTyped<T>>(
+    arms: Vec<MatchArm<TypedExpression<T>>>,
+) -> Vec<MatchArm<V>> {
+    arms.into_iter()
+        .map(|arm| MatchArm {
+            variant: arm.variant,
+            binding: arm.binding,
+            expression: box V::from_typed(*arm.expression),
+        }).collect()
+}
+
+// whether a value of type `ty` can be passed where `expected` is, the sizes of the generic arrays
+// in `expected` being bound in `sizes` on the way
+fn infer_sizes(expected: &Type, ty: &Type, sizes: &mut HashMap<String, usize>) -> bool {
+    match (expected, ty) {
+        (Type::GenericArray(expected, id), Type::Array(ty, size)) => {
+            *sizes.entry(id.clone()).or_insert(*size) == *size && infer_sizes(expected, ty, sizes)
+        }
+        (Type::Array(expected, expected_size), Type::Array(ty, size)) => {
+            expected_size == size && infer_sizes(expected, ty, sizes)
+        }
+        (Type::Struct(expected), Type::Struct(members)) => {
+            expected.len() == members.len()
+                && expected
+                    .iter()
+                    .zip(members)
+                    .all(|((e, expected), (id, ty))| e == id && infer_sizes(expected, ty, sizes))
+        }
+        (Type::Tuple(expected), Type::Tuple(elements)) => {
+            expected.len() == elements.len()
+                && expected
+                    .iter()
+                    .zip(elements)
+                    .all(|(expected, ty)| infer_sizes(expected, ty, sizes))
+        }
+        (Type::Enum(e, expected), Type::Enum(id, variants)) => {
+            e == id
+                && expected.len() == variants.len()
+                && expected
+                    .iter()
+                    .zip(variants)
+                    .all(|((e, expected), (id, ty))| e == id && infer_sizes(expected, ty, sizes))
+        }
+        (Type::Option(expected), Type::Option(ty)) => infer_sizes(expected, ty, sizes),
+        (expected, ty) => expected == ty,
+    }
+}
+
+// the types in a signature, as in `field, bool`
+fn type_list(types: &[Type]) -> String {
+    types
+        .iter()
+        .map(|ty| ty.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// rejects the integers of a width other than 8, 16, 32 or 64, and the enums a match could not be
+// written for, which have no variants or two variants of the same name
+fn check_type(ty: &Type) -> Result<(), Error> {
+    match *ty {
+        Type::FieldElement | Type::Boolean | Type::GenericArray(..) => Ok(()),
+        Type::Uint(bitwidth) => check_bitwidth(bitwidth),
+        Type::Array(ref inner, _) | Type::Option(ref inner) => check_type(inner),
+        Type::Struct(ref members) => members.iter().map(|(_, ty)| check_type(ty)).collect(),
+        Type::Tuple(ref elements) => elements.iter().map(check_type).collect(),
+        Type::Enum(ref id, ref variants) => {
+            if variants.is_empty() {
+                return Err(Error::new(format!("{} has no variants", id)));
+            }
+            for (i, (variant, ty)) in variants.iter().enumerate() {
+                if variants[..i].iter().any(|(other, _)| other == variant) {
+                    return Err(Error::new(format!("duplicate variant {} in {}", variant, id)));
+                }
+                check_type(ty)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn check_bitwidth(bitwidth: usize) -> Result<(), Error> {
+    match bitwidth {
+        8 | 16 | 32 | 64 => Ok(()),
+        _ => Err(Error::new(format!("unsupported integer type u{}", bitwidth))),
+    }
+}
+
+// whether `==` and `!=` compare values of type `ty` slot by slot
+fn is_structural(ty: &Type) -> bool {
+    match *ty {
+        Type::Array(..) | Type::Struct(..) => true,
+        _ => false,
+    }
+}
+
+// What a name resolves to in a scope
+struct Declaration {
+    _type: Type,
+    // loop variables and match bindings cannot be assigned to
+    mutable: bool,
+    // the bounds of a loop variable, which takes each value from the first to one before the last
+    range: Option<(usize, usize)>,
+}
+
+pub struct Checker {
+    // innermost last. A name can only be declared once per scope, but can shadow the same name in
+    // an enclosing scope until the end of its own
+    scopes: Vec<HashMap<String, Declaration>>,
+    // the signatures of the functions checked so far and of the instances of generic functions,
+    // along with the position of the function they come from in the program. A function can only
+    // call the ones before it
+    functions: HashMap<String, (usize, Signature)>,
+    // the generic functions declared so far, along with their position
+    generics: HashMap<String, (usize, absy::Function)>,
+    // the generic functions called so far, whether their instance could be inferred or not
+    called: HashSet<String>,
+    // the instances called but not checked yet: their id, the sizes they are instantiated with,
+    // and the position of their generic function along with its definition
+    instances: Vec<(String, HashMap<String, usize>, usize, absy::Function)>,
+    // the number of functions declared so far
+    declared: usize,
+    // the position of the function being checked
+    position: usize,
+    // the sizes the function being checked is instantiated with
+    sizes: HashMap<String, usize>,
+    // what the function being checked returns
+    returns: Vec<Type>,
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        Checker {
+            scopes: vec![],
+            functions: HashMap::new(),
+            generics: HashMap::new(),
+            called: HashSet::new(),
+            instances: vec![],
+            declared: 0,
+            position: 0,
+            sizes: HashMap::new(),
+            returns: vec![],
+        }
+    }
+
+    // checks each function in turn. The body of a generic function is only checked for each of
+    // its instances, as the types in it are not known until its sizes are, so a generic function
+    // which is never called is rejected rather than left unchecked. A well typed program is then
+    // flattened, to report the constants which are only known once loops are unrolled and calls
+    // inlined, such as indices out of bounds
+    pub fn check_program<T: Field>(&mut self, p: absy::Program) -> Result<Program<T>, Vec<Error>> {
+        let mut errors = vec![];
+        let mut functions = vec![];
+
+        if !p.functions.iter().any(|f| f.id == "main") {
+            errors.push(Error::new("no main function"));
+        }
+
+        for f in p.functions {
+            if self.functions.contains_key(&f.id) || self.generics.contains_key(&f.id) {
+                errors.push(Error::new(format!("duplicate function {}", f.id)));
+                continue;
+            }
+
+            // generic functions are checked once per instance, when they are first called
+            if !f.generics.is_empty() {
+                if f.id == "main" {
+                    errors.push(Error::new("main cannot be generic"));
+                    continue;
+                }
+                self.generics.insert(f.id.clone(), (self.declared, f));
+                self.declared += 1;
+                continue;
+            }
+
+            match self.check_function(f) {
+                Ok(f) => functions.push(f),
+                Err(e) => errors.extend(e),
+            }
+
+            // the instances the function calls, and the ones these call in turn
+            while let Some((id, sizes, position, f)) = self.instances.pop() {
+                match self.check_definition(id, f, position, sizes) {
+                    Ok(f) => functions.push(f),
+                    Err(e) => errors.extend(e),
+                }
+            }
+        }
+
+        let mut uncalled: Vec<_> = self
+            .generics
+            .iter()
+            .filter(|&(id, _)| !self.called.contains(id))
+            .map(|(id, &(position, _))| (position, id))
+            .collect();
+        uncalled.sort();
+        for (_, id) in uncalled {
+            errors.push(Error::new(format!("generic function {} is never called", id)));
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let program = Program::with_functions(functions);
+        match program.try_flatten() {
+            Ok(_) => Ok(program),
+            Err(e) => Err(e.into_iter().map(Error::new).collect()),
+        }
+    }
+
+    // the function becomes callable once it is checked, so it cannot call itself. The instances
+    // of generic functions it calls are only checked by `check_program`
+    pub fn check_function<T: Field>(
+        &mut self,
+        f: absy::Function,
+    ) -> Result<Function<T>, Vec<Error>> {
+        let position = self.declared;
+        self.declared += 1;
+        self.check_definition(f.id.clone(), f, position, HashMap::new())
+    }
+
+    // checks `f` under the name `id`, with its generic sizes set to `sizes`
+    fn check_definition<T: Field>(
+        &mut self,
+        id: String,
+        f: absy::Function,
+        position: usize,
+        sizes: HashMap<String, usize>,
+    ) -> Result<Function<T>, Vec<Error>> {
+        let mut arguments = vec![];
+        let mut errors = vec![];
+
+        self.scopes = vec![HashMap::new()];
+        self.position = position;
+        self.sizes = sizes;
+
+        let mut returns = vec![];
+        for ty in f.returns {
+            match self.resolve(&ty) {
+                Ok(ty) => returns.push(ty),
+                Err(e) => errors.push(e),
+            }
+        }
+        self.returns = returns.clone();
+
+        for p in f.arguments {
+            let ty = match self.resolve(&p.id._type) {
+                Ok(ty) => ty,
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+            if let Err(e) = self.declare(&p.id.id, ty.clone(), true) {
+                errors.push(e);
+            }
+            arguments.push(Parameter::new(Variable::new(p.id.id, ty), p.private));
+        }
+
+        // a function returns once, as the last statement of its body: a return in a loop is not
+        // reached if the loop does not run, and one anywhere else would skip what follows it
+        let count = f.statements.len();
+        let mut returns_values = false;
+        for (i, s) in f.statements.iter().enumerate() {
+            if let absy::Statement::Return(..) = *s {
+                if i + 1 == count {
+                    returns_values = true;
+                } else {
+                    errors.push(Error::new(
+                        "expected return to be the last statement of the function",
+                    ));
+                }
+            }
+        }
+        if !returns.is_empty() && !returns_values {
+            errors.push(Error::new(format!(
+                "expected to return ({}), found no return statement",
+                type_list(&returns)
+            )));
+        }
+
+        let (statements, e) = self.check_statements(f.statements);
+        errors.extend(e);
+
+        let function = Function::new(id, arguments, returns, statements);
+        self.functions.insert(function.id().clone(), (position, function.signature()));
+
+        if errors.is_empty() {
+            Ok(function)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // `ty` with the sizes of the function being checked
+    fn resolve(&self, ty: &Type) -> Result<Type, Error> {
+        let ty = ty
+            .instantiate(&self.sizes)
+            .map_err(|e| Error::new(e.to_string()))?;
+        check_type(&ty)?;
+        Ok(ty)
+    }
+
+    fn size(&self, size: absy::Size) -> Result<usize, Error> {
+        match size {
+            absy::Size::Constant(size) => Ok(size),
+            absy::Size::Generic(id) => match self.sizes.get(&id) {
+                Some(size) => Ok(*size),
+                None => Err(Error::new(format!("undeclared size {}", id))),
+            },
+        }
+    }
+
+    // the instance of the generic function `f` declared at `position` that can be called with
+    // arguments of types `types`, which is registered to be checked if it is new
+    fn instantiate(
+        &mut self,
+        id: &str,
+        position: usize,
+        f: absy::Function,
+        types: &[Type],
+    ) -> Result<(String, Signature), Error> {
+        let inputs: Vec<_> = f.arguments.iter().map(|p| p.id._type.clone()).collect();
+
+        let mut sizes = HashMap::new();
+        if inputs.len() != types.len()
+            || !inputs
+                .iter()
+                .zip(types)
+                .all(|(expected, ty)| infer_sizes(expected, ty, &mut sizes))
+        {
+            return Err(Error::new(format!(
+                "expected {} to be called with ({}), found ({})",
+                id,
+                type_list(&inputs),
+                type_list(types)
+            )));
+        }
+        sizes.retain(|size, _| f.generics.contains(size));
+
+        let mut instantiated = vec![];
+        for size in &f.generics {
+            match sizes.get(size) {
+                Some(value) => instantiated.push(value.to_string()),
+                None => return Err(Error::new(format!("cannot infer size {} of {}", size, id))),
+            }
+        }
+        let instance = format!("{}<{}>", id, instantiated.join(", "));
+
+        if !self.functions.contains_key(&instance) {
+            let resolve = |types: &[Type]| {
+                types
+                    .iter()
+                    .map(|ty| ty.instantiate(&sizes))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| Error::new(e.to_string()))
+            };
+            let signature = Signature {
+                inputs: resolve(&inputs)?,
+                outputs: resolve(&f.returns)?,
+            };
+            self.functions.insert(instance.clone(), (position, signature));
+            self.instances.push((instance.clone(), sizes, position, f));
+        }
+
+        let signature = self.functions[&instance].1.clone();
+        Ok((instance, signature))
+    }
+
+    fn declare(&mut self, id: &str, ty: Type, mutable: bool) -> Result<(), Error> {
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.contains_key(id) {
+            return Err(Error::new(format!("{} is already declared in this scope", id)));
+        }
+        scope.insert(
+            id.to_string(),
+            Declaration {
+                _type: ty,
+                mutable,
+                range: None,
+            },
+        );
+        Ok(())
+    }
+
+    fn lookup(&self, id: &str) -> Option<&Declaration> {
+        self.scopes.iter().rev().filter_map(|scope| scope.get(id)).next()
+    }
+
+    // the smallest and largest values of an index built from constants and loop variables, which
+    // are known when flattening. A difference which can be negative wraps around to the top of
+    // the field. Other constant indices are only bounded by `check_program`, once flattened
+    fn index_range<T: Field>(&self, e: &FieldElement<T>) -> Option<(BigUint, BigUint)> {
+        match *e {
+            FieldElement::Value(ref v) => Some((v.to_biguint(), v.to_biguint())),
+            FieldElement::Identifier(ref v) => match self.lookup(v.name()).and_then(|d| d.range) {
+                Some((start, end)) if start < end => {
+                    Some((BigUint::from(start), BigUint::from(end - 1)))
+                }
+                _ => None,
+            },
+            FieldElement::Add(ref e1, ref e2) => {
+                let ((min1, max1), (min2, max2)) = (self.index_range(e1)?, self.index_range(e2)?);
+                Some((min1 + min2, max1 + max2))
+            }
+            FieldElement::Sub(ref e1, ref e2) => {
+                let ((min1, max1), (min2, max2)) = (self.index_range(e1)?, self.index_range(e2)?);
+                match min1 >= max2 {
+                    true => Some((min1 - max2, max1 - min2)),
+                    false => Some((BigUint::from(0u32), T::modulus() - 1u32)),
+                }
+            }
+            FieldElement::Mult(ref e1, ref e2) => {
+                let ((min1, max1), (min2, max2)) = (self.index_range(e1)?, self.index_range(e2)?);
+                Some((min1 * min2, max1 * max2))
+            }
+            FieldElement::Pow(ref e, exponent) => {
+                let (min, max) = self.index_range(e)?;
+                Some((pow(min, exponent), pow(max, exponent)))
+            }
+            _ => None,
+        }
+    }
+
+    // checks each statement, collecting the errors of all of them
+    fn check_statements<T: Field>(
+        &mut self,
+        statements: Vec<absy::Statement>,
+    ) -> (Vec<Statement<T>>, Vec<Error>) {
+        let mut checked = vec![];
+        let mut errors = vec![];
+
+        for s in statements {
+            match self.check_statement(s) {
+                Ok(s) => checked.push(s),
+                Err(e) => errors.extend(e),
+            }
+        }
+
+        (checked, errors)
+    }
+
+    fn check_statement<T: Field>(
+        &mut self,
+        s: absy::Statement,
+    ) -> Result<Statement<T>, Vec<Error>> {
+        match s {
+            absy::Statement::Definition(v, e) => {
+                let ty = self.resolve(&v._type).map_err(|e| vec![e])?;
+                let v = absy::Variable::new(v.id, ty);
+                let repr = e.to_string();
+                let e = self.check_expression(e);
+
+                // declare the variable even if its definition is wrong, so that its uses do not
+                // get reported as well
+                self.declare(&v.id, v._type.clone(), true).map_err(|e| vec![e])?;
+
+                let e = e.map_err(|e| vec![e])?;
+                if e.get_type() != v._type {
+                    return Err(vec![Error::new(format!(
+                        "expected {} to be of type {}, found {} of type {}",
+                        v.id,
+                        v._type,
+                        repr,
+                        e.get_type()
+                    ))]);
+                }
+
+                Ok(Statement::Definition(Variable::new(v.id, v._type), e.into_boxed()))
+            }
+            absy::Statement::MultipleDefinition(vars, e) => {
+                let vars = vars
+                    .into_iter()
+                    .map(|v| self.resolve(&v._type).map(|ty| absy::Variable::new(v.id, ty)))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| vec![e])?;
+                let repr = e.to_string();
+                let e = self.check_expression(e);
+
+                let mut errors = vec![];
+                for v in &vars {
+                    if let Err(e) = self.declare(&v.id, v._type.clone(), true) {
+                        errors.push(e);
+                    }
+                }
+                if !errors.is_empty() {
+                    return Err(errors);
+                }
+
+                let e = e.map_err(|e| vec![e])?;
+                let types: Vec<_> = vars.iter().map(|v| v._type.clone()).collect();
+                if e.get_type() != Type::Tuple(types.clone()) {
+                    return Err(vec![Error::new(format!(
+                        "expected {} to be of type ({}), found {} of type {}",
+                        vars.iter()
+                            .map(|v| v.id.clone())
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        type_list(&types),
+                        repr,
+                        e.get_type()
+                    ))]);
+                }
+
+                Ok(Statement::MultipleDefinition(
+                    vars.into_iter()
+                        .map(|v| Variable::new(v.id, v._type))
+                        .collect(),
+                    e.into_boxed(),
+                ))
+            }
+            absy::Statement::Assignment(id, e) => {
+                let ty = match self.lookup(&id) {
+                    Some(d) if d.mutable => d._type.clone(),
+                    Some(_) => {
+                        return Err(vec![Error::new(format!(
+                            "cannot assign to immutable variable {}",
+                            id
+                        ))])
+                    }
+                    None => return Err(vec![Error::new(format!("undeclared variable {}", id))]),
+                };
+
+                let repr = e.to_string();
+                let e = self.check_expression(e).map_err(|e| vec![e])?;
+                if e.get_type() != ty {
+                    return Err(vec![Error::new(format!(
+                        "expected {} to be of type {}, found {} of type {}",
+                        id,
+                        ty,
+                        repr,
+                        e.get_type()
+                    ))]);
+                }
+
+                Ok(Statement::Assignment(Variable::new(id, ty), e.into_boxed()))
+            }
+            absy::Statement::Return(e) => {
+                // the function body is the outermost scope
+                if self.scopes.len() > 1 {
+                    return Err(vec![Error::new(
+                        "expected return to be the last statement of the function",
+                    )]);
+                }
+
+                let e = e
+                    .into_iter()
+                    .map(|e| self.check_expression(e))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| vec![e])?;
+
+                let types: Vec<_> = e.iter().map(TypedExpression::get_type).collect();
+                if types != self.returns {
+                    return Err(vec![Error::new(format!(
+                        "expected to return ({}), found ({})",
+                        type_list(&self.returns),
+                        type_list(&types)
+                    ))]);
+                }
+
+                Ok(Statement::Return(e.into_iter().map(|e| e.into_boxed()).collect()))
+            }
+            absy::Statement::For(v, start, end, statements) => {
+                if v._type != Type::FieldElement {
+                    return Err(vec![Error::new(format!(
+                        "expected loop variable {} to be of type field, found {}",
+                        v.id,
+                        v._type
+                    ))]);
+                }
+                let start = self.size(start).map_err(|e| vec![e])?;
+                let end = self.size(end).map_err(|e| vec![e])?;
+
+                // the loop variable and the body share a scope
+                self.scopes.push(HashMap::new());
+                self.declare(&v.id, v._type.clone(), false).unwrap();
+                self.scopes.last_mut().unwrap().get_mut(&v.id).unwrap().range = Some((start, end));
+                let (statements, errors) = self.check_statements(statements);
+                self.scopes.pop();
+
+                if errors.is_empty() {
+                    Ok(Statement::For(Variable::new(v.id, v._type), start, end, statements))
+                } else {
+                    Err(errors)
+                }
+            }
+            absy::Statement::AssertEq(e1, e2) => {
+                let (r1, r2) = (e1.to_string(), e2.to_string());
+                let e1 = self.check_expression(e1).map_err(|e| vec![e])?;
+                let e2 = self.check_expression(e2).map_err(|e| vec![e])?;
+                if e1.get_type() != e2.get_type() {
+                    return Err(vec![Error::new(format!(
+                        "expected {} and {} to be of the same type, found {} and {}",
+                        r1,
+                        r2,
+                        e1.get_type(),
+                        e2.get_type()
+                    ))]);
+                }
+
+                Ok(Statement::AssertEq(e1.into_boxed(), e2.into_boxed()))
+            }
+        }
+    }
+
+    fn check_field_operands<T: Field>(
+        &mut self,
+        operator: &str,
+        e1: absy::Expression,
+        e2: absy::Expression,
+    ) -> Result<(FieldElement<T>, FieldElement<T>), Error> {
+        let (r1, r2) = (e1.to_string(), e2.to_string());
+        match (self.check_expression(e1)?, self.check_expression(e2)?) {
+            (TypedExpression::FieldElement(e1), TypedExpression::FieldElement(e2)) => Ok((e1, e2)),
+            (e1, e2) => Err(Error::new(format!(
+                "cannot apply {} to {} of type {} and {} of type {}",
+                operator,
+                r1,
+                e1.get_type(),
+                r2,
+                e2.get_type()
+            ))),
+        }
+    }
+
+    // `<`, `<=`, `>` and `>=` apply to two field elements which fit in `n - 2` bits, `n` being the
+    // bit width of the field, which is checked here for constants
+    fn check_comparison_operands<T: Field>(
+        &mut self,
+        operator: &str,
+        e1: absy::Expression,
+        e2: absy::Expression,
+    ) -> Result<(FieldElement<T>, FieldElement<T>), Error> {
+        let (e1, e2) = self.check_field_operands::<T>(operator, e1, e2)?;
+        let width = T::get_required_bits() - 2;
+        for e in vec![&e1, &e2] {
+            if let FieldElement::Value(ref v) = *e {
+                if v.to_biguint().bits() > width {
+                    return Err(Error::new(format!(
+                        "cannot apply {} to {}, which does not fit in {} bits",
+                        operator, v, width
+                    )));
+                }
+            }
+        }
+        Ok((e1, e2))
+    }
+
+    // `+`, `-` and `*` apply to two field elements or to two integers of the same width
+    fn check_arithmetic<T: Field>(
+        &mut self,
+        operator: &str,
+        e1: absy::Expression,
+        e2: absy::Expression,
+        field: fn(Box<FieldElement<T>>, Box<FieldElement<T>>) -> FieldElement<T>,
+        uint: fn(Box<Uint<T>>, Box<Uint<T>>) -> Uint<T>,
+    ) -> Result<TypedExpression<T>, Error> {
+        let (r1, r2) = (e1.to_string(), e2.to_string());
+        match (self.check_expression(e1)?, self.check_expression(e2)?) {
+            (TypedExpression::FieldElement(e1), TypedExpression::FieldElement(e2)) => {
+                Ok(TypedExpression::FieldElement(field(box e1, box e2)))
+            }
+            (TypedExpression::Uint(e1), TypedExpression::Uint(e2))
+                if e1.get_type() == e2.get_type() =>
+            {
+                Ok(TypedExpression::Uint(uint(box e1, box e2)))
+            }
+            (e1, e2) => Err(Error::new(format!(
+                "cannot apply {} to {} of type {} and {} of type {}",
+                operator,
+                r1,
+                e1.get_type(),
+                r2,
+                e2.get_type()
+            ))),
+        }
+    }
+
+    // `^`, `v` and `xor` apply to two booleans, or bit by bit to two integers of the same width
+    fn check_bitwise<T: Field>(
+        &mut self,
+        operator: &str,
+        e1: absy::Expression,
+        e2: absy::Expression,
+        boolean: fn(Box<Boolean<T>>, Box<Boolean<T>>) -> Boolean<T>,
+        uint: fn(Box<Uint<T>>, Box<Uint<T>>) -> Uint<T>,
+    ) -> Result<TypedExpression<T>, Error> {
+        let (r1, r2) = (e1.to_string(), e2.to_string());
+        match (self.check_expression(e1)?, self.check_expression(e2)?) {
+            (TypedExpression::Boolean(e1), TypedExpression::Boolean(e2)) => {
+                Ok(TypedExpression::Boolean(boolean(box e1, box e2)))
+            }
+            (TypedExpression::Uint(e1), TypedExpression::Uint(e2))
+                if e1.get_type() == e2.get_type() =>
+            {
+                Ok(TypedExpression::Uint(uint(box e1, box e2)))
+            }
+            (e1, e2) => Err(Error::new(format!(
+                "cannot apply {} to {} of type {} and {} of type {}",
+                operator,
+                r1,
+                e1.get_type(),
+                r2,
+                e2.get_type()
+            ))),
+        }
+    }
+
+    // an integer, and the constant number of bits to shift it by, below its bitwidth
+    fn check_shift<T: Field>(
+        &mut self,
+        operator: &str,
+        e: absy::Expression,
+        by: absy::Expression,
+    ) -> Result<(Uint<T>, usize), Error> {
+        let amount = match by {
+            absy::Expression::FieldConstant(ref v) => v.to_usize(),
+            _ => {
+                return Err(Error::new(format!(
+                    "expected shift amount {} to be a constant",
+                    by
+                )))
+            }
+        };
+
+        let repr = e.to_string();
+        match self.check_expression(e)? {
+            TypedExpression::Uint(e) => match amount {
+                Some(amount) if amount < e.bitwidth() => Ok((e, amount)),
+                _ => Err(Error::new(format!(
+                    "shift amount {} is out of range for {} of type {}",
+                    by,
+                    repr,
+                    e.get_type()
+                ))),
+            },
+            e => Err(Error::new(format!(
+                "cannot apply {} to {} of type {}",
+                operator,
+                repr,
+                e.get_type()
+            ))),
+        }
+    }
+
+    fn check_boolean_operands<T: Field>(
+        &mut self,
+        operator: &str,
+        e1: absy::Expression,
+        e2: absy::Expression,
+    ) -> Result<(Boolean<T>, Boolean<T>), Error> {
+        let (r1, r2) = (e1.to_string(), e2.to_string());
+        match (self.check_expression(e1)?, self.check_expression(e2)?) {
+            (TypedExpression::Boolean(e1), TypedExpression::Boolean(e2)) => Ok((e1, e2)),
+            (e1, e2) => Err(Error::new(format!(
+                "cannot apply {} to {} of type {} and {} of type {}",
+                operator,
+                r1,
+                e1.get_type(),
+                r2,
+                e2.get_type()
+            ))),
+        }
+    }
+
+    // `==` and `!=` apply to two field elements or to two booleans
+    fn check_equality<T: Field>(
+        &mut self,
+        equal: bool,
+        e1: absy::Expression,
+        e2: absy::Expression,
+    ) -> Result<Boolean<T>, Error> {
+        let (r1, r2) = (e1.to_string(), e2.to_string());
+        match (self.check_expression(e1)?, self.check_expression(e2)?) {
+            (TypedExpression::Boolean(e1), TypedExpression::Boolean(e2)) => Ok(match equal {
+                true => Boolean::Eq(box e1, box e2),
+                false => Boolean::Xor(box e1, box e2),
+            }),
+            (TypedExpression::FieldElement(e1), TypedExpression::FieldElement(e2)) => {
+                Ok(match equal {
+                    true => Boolean::FieldEq(box e1, box e2),
+                    false => Boolean::FieldNeq(box e1, box e2),
+                })
+            }
+            (e1, e2) if is_structural(&e1.get_type()) && e1.get_type() == e2.get_type() => {
+                Ok(match equal {
+                    true => Boolean::StructuralEq(e1.into_boxed(), e2.into_boxed()),
+                    false => Boolean::StructuralNeq(e1.into_boxed(), e2.into_boxed()),
+                })
+            }
+            (e1, e2) => Err(Error::new(format!(
+                "cannot apply {} to {} of type {} and {} of type {}",
+                if equal { "==" } else { "!=" },
+                r1,
+                e1.get_type(),
+                r2,
+                e2.get_type()
+            ))),
+        }
+    }
+
+    fn check_expression<T: Field>(
+        &mut self,
+        e: absy::Expression,
+    ) -> Result<TypedExpression<T>, Error> {
+        match e {
+            absy::Expression::FieldConstant(v) => match T::try_from_biguint(v.clone()) {
+                Some(v) => Ok(TypedExpression::FieldElement(FieldElement::Value(v))),
+                None => Err(Error::new(format!(
+                    "constant {} does not fit in a field of modulus {}",
+                    v,
+                    T::modulus()
+                ))),
+            },
+            absy::Expression::BooleanConstant(v) => {
+                Ok(TypedExpression::Boolean(Boolean::Value(v)))
+            }
+            absy::Expression::UintConstant(v, bitwidth) => {
+                check_bitwidth(bitwidth)?;
+                if bitwidth < 64 && v >> bitwidth != 0 {
+                    return Err(Error::new(format!(
+                        "constant {} does not fit in u{}",
+                        v,
+                        bitwidth
+                    )));
+                }
+                Ok(TypedExpression::Uint(Uint::Value(v, bitwidth)))
+            }
+            absy::Expression::Identifier(id) => match self.lookup(&id) {
+                Some(d) => Ok(TypedExpression::identifier(Variable::new(id, d._type.clone()))),
+                None => Err(Error::new(format!("undeclared variable {}", id))),
+            },
+            absy::Expression::Add(e1, e2) => {
+                self.check_arithmetic("+", *e1, *e2, FieldElement::Add, Uint::Add)
+            }
+            absy::Expression::Sub(e1, e2) => {
+                self.check_arithmetic("-", *e1, *e2, FieldElement::Sub, Uint::Sub)
+            }
+            absy::Expression::Mult(e1, e2) => {
+                self.check_arithmetic("*", *e1, *e2, FieldElement::Mult, Uint::Mult)
+            }
+            absy::Expression::Div(e1, e2) => {
+                let (e1, e2) = self.check_field_operands("/", *e1, *e2)?;
+                Ok(TypedExpression::FieldElement(FieldElement::Div(box e1, box e2)))
+            }
+            absy::Expression::Pow(e1, e2) => {
+                // the exponent is unrolled into multiplications, so it must fit in a `usize`
+                let exponent = match *e2 {
+                    absy::Expression::FieldConstant(ref v) => v.to_usize(),
+                    _ => None,
+                };
+                let exponent = match exponent {
+                    Some(exponent) => exponent,
+                    None => {
+                        return Err(Error::new(format!(
+                            "expected exponent {} to be a constant",
+                            e2
+                        )))
+                    }
+                };
+                let repr = e1.to_string();
+                match self.check_expression(*e1)? {
+                    TypedExpression::FieldElement(e1) => Ok(TypedExpression::FieldElement(
+                        FieldElement::Pow(box e1, exponent),
+                    )),
+                    e1 => Err(Error::new(format!(
+                        "cannot apply ** to {} of type {}",
+                        repr,
+                        e1.get_type()
+                    ))),
+                }
+            }
+            absy::Expression::And(e1, e2) => {
+                self.check_bitwise("^", *e1, *e2, Boolean::And, Uint::And)
+            }
+            absy::Expression::Or(e1, e2) => {
+                self.check_bitwise("v", *e1, *e2, Boolean::Or, Uint::Or)
+            }
+            absy::Expression::Not(e) => {
+                let repr = e.to_string();
+                match self.check_expression(*e)? {
+                    TypedExpression::Boolean(e) => {
+                        Ok(TypedExpression::Boolean(Boolean::Not(box e)))
+                    }
+                    TypedExpression::Uint(e) => Ok(TypedExpression::Uint(Uint::Not(box e))),
+                    e => Err(Error::new(format!(
+                        "cannot apply ! to {} of type {}",
+                        repr,
+                        e.get_type()
+                    ))),
+                }
+            }
+            absy::Expression::Xor(e1, e2) => {
+                self.check_bitwise("xor", *e1, *e2, Boolean::Xor, Uint::Xor)
+            }
+            absy::Expression::LeftShift(e1, e2) => {
+                let (e, by) = self.check_shift("<<", *e1, *e2)?;
+                Ok(TypedExpression::Uint(Uint::LeftShift(box e, by)))
+            }
+            absy::Expression::RightShift(e1, e2) => {
+                let (e, by) = self.check_shift(">>", *e1, *e2)?;
+                Ok(TypedExpression::Uint(Uint::RightShift(box e, by)))
+            }
+            absy::Expression::Eq(e1, e2) => {
+                Ok(TypedExpression::Boolean(self.check_equality(true, *e1, *e2)?))
+            }
+            absy::Expression::Neq(e1, e2) => {
+                Ok(TypedExpression::Boolean(self.check_equality(false, *e1, *e2)?))
+            }
+            absy::Expression::Lt(e1, e2) => {
+                let (e1, e2) = self.check_comparison_operands("<", *e1, *e2)?;
+                Ok(TypedExpression::Boolean(Boolean::Lt(box e1, box e2)))
+            }
+            absy::Expression::Le(e1, e2) => {
+                let (e1, e2) = self.check_comparison_operands("<=", *e1, *e2)?;
+                Ok(TypedExpression::Boolean(Boolean::Le(box e1, box e2)))
+            }
+            absy::Expression::Gt(e1, e2) => {
+                let (e1, e2) = self.check_comparison_operands(">", *e1, *e2)?;
+                Ok(TypedExpression::Boolean(Boolean::Gt(box e1, box e2)))
+            }
+            absy::Expression::Ge(e1, e2) => {
+                let (e1, e2) = self.check_comparison_operands(">=", *e1, *e2)?;
+                Ok(TypedExpression::Boolean(Boolean::Ge(box e1, box e2)))
+            }
+            absy::Expression::ArrayValue(values) => {
+                let mut checked = vec![];
+                for e in values {
+                    let repr = e.to_string();
+                    let e = self.check_expression(e)?;
+                    if let Some(first) = checked.first().map(TypedExpression::get_type) {
+                        if e.get_type() != first {
+                            return Err(Error::new(format!(
+                                "expected an element of type {}, found {} of type {}",
+                                first,
+                                repr,
+                                e.get_type()
+                            )));
+                        }
+                    }
+                    checked.push(e);
+                }
+
+                if checked.is_empty() {
+                    return Err(Error::new("empty arrays are not supported"));
+                }
+
+                Ok(TypedExpression::Array(ArrayExpression::Value(checked)))
+            }
+            absy::Expression::StructValue(members) => {
+                let members = members
+                    .into_iter()
+                    .map(|(id, e)| self.check_expression(e).map(|e| (id, e.into_boxed())))
+                    .collect::<Result<_, _>>()?;
+
+                Structure::value(members)
+                    .map(TypedExpression::Structure)
+                    .map_err(|e| Error::new(e.to_string()))
+            }
+            absy::Expression::Member(s, id) => {
+                let repr = s.to_string();
+                match self.check_expression(*s)? {
+                    TypedExpression::Structure(s) => {
+                        let ty = match s.get_type().member(&id) {
+                            Some((_, ty)) => ty.clone(),
+                            None => {
+                                return Err(Error::new(format!(
+                                    "{} of type {} has no member {}",
+                                    repr,
+                                    s.get_type(),
+                                    id
+                                )))
+                            }
+                        };
+                        Ok(TypedExpression::member(s, id, ty))
+                    }
+                    s => Err(Error::new(format!(
+                        "{} of type {} has no member {}",
+                        repr,
+                        s.get_type(),
+                        id
+                    ))),
+                }
+            }
+            absy::Expression::TupleValue(elements) => {
+                let elements = elements
+                    .into_iter()
+                    .map(|e| self.check_expression(e).map(|e| e.into_boxed()))
+                    .collect::<Result<_, _>>()?;
+
+                Tuple::value(elements)
+                    .map(TypedExpression::Tuple)
+                    .map_err(|e| Error::new(e.to_string()))
+            }
+            absy::Expression::Element(t, index) => {
+                let repr = t.to_string();
+                match self.check_expression(*t)? {
+                    TypedExpression::Tuple(t) => {
+                        let ty = match t.get_type().element(index) {
+                            Some((_, ty)) => ty.clone(),
+                            None => {
+                                return Err(Error::new(format!(
+                                    "{} of type {} has no element {}",
+                                    repr,
+                                    t.get_type(),
+                                    index
+                                )))
+                            }
+                        };
+                        Ok(TypedExpression::element(t, index, ty))
+                    }
+                    t => Err(Error::new(format!(
+                        "{} of type {} has no element {}",
+                        repr,
+                        t.get_type(),
+                        index
+                    ))),
+                }
+            }
+            absy::Expression::EnumValue(ty, id, payload) => {
+                let ty = self.resolve(&ty)?;
+                if let Type::Enum(..) = ty {
+                    let payload = self.check_expression(*payload)?.into_boxed();
+                    Enumeration::variant(ty, id, payload)
+                        .map(TypedExpression::Enumeration)
+                        .map_err(|e| Error::new(e.to_string()))
+                } else {
+                    Err(Error::new(format!("{} is not an enum", ty)))
+                }
+            }
+            absy::Expression::Match(e, arms) => {
+                let repr = e.to_string();
+                let e = match self.check_expression(*e)? {
+                    TypedExpression::Enumeration(e) => e,
+                    e => {
+                        return Err(Error::new(format!(
+                            "cannot match on {} of type {}",
+                            repr,
+                            e.get_type()
+                        )))
+                    }
+                };
+                let ty = e.get_type();
+
+                let mut checked: Vec<MatchArm<TypedExpression<T>>> = vec![];
+                for (variant, binding, arm) in arms {
+                    let payload = match ty.variant(&variant) {
+                        Some((_, payload)) => payload.clone(),
+                        None => {
+                            return Err(Error::new(format!("{} has no variant {}", ty, variant)))
+                        }
+                    };
+                    if checked.iter().any(|arm| arm.variant == variant) {
+                        return Err(Error::new(format!("duplicate arm {}", variant)));
+                    }
+
+                    // the binding is only in scope in its arm
+                    self.scopes.push(HashMap::new());
+                    self.declare(&binding, payload.clone(), false).unwrap();
+                    let arm = self.check_expression(arm);
+                    self.scopes.pop();
+                    let arm = arm?;
+
+                    if let Some(first) = checked.first() {
+                        if first.expression.get_type() != arm.get_type() {
+                            return Err(Error::new(format!(
+                                "expected the arms of the match on {} to be of the same type, \
+                                 found {} and {}",
+                                repr,
+                                first.expression.get_type(),
+                                arm.get_type()
+                            )));
+                        }
+                    }
+
+                    checked.push(MatchArm {
+                        variant,
+                        binding: Variable::new(binding, payload),
+                        expression: box arm,
+                    });
+                }
+
+                let missing: Vec<_> = match ty {
+                    Type::Enum(_, ref variants) => variants
+                        .iter()
+                        .map(|(id, _)| id.clone())
+                        .filter(|id| !checked.iter().any(|arm| &arm.variant == id))
+                        .collect(),
+                    _ => unreachable!(),
+                };
+                if !missing.is_empty() {
+                    return Err(Error::new(format!(
+                        "match on {} does not cover {}",
+                        repr,
+                        missing.join(", ")
+                    )));
+                }
+
+                let result = checked[0].expression.get_type();
+                Ok(TypedExpression::match_arms(e, checked, result))
+            }
+            absy::Expression::SomeValue(e) => {
+                let e = self.check_expression(*e)?;
+                Ok(TypedExpression::Optional(Optional::Some(e.into_boxed())))
+            }
+            absy::Expression::NoneValue(ty) => {
+                Ok(TypedExpression::Optional(Optional::None(self.resolve(&ty)?)))
+            }
+            absy::Expression::IsSome(e) => {
+                let repr = e.to_string();
+                match self.check_expression(*e)? {
+                    TypedExpression::Optional(o) => {
+                        Ok(TypedExpression::Boolean(Boolean::IsSome(box o)))
+                    }
+                    e => Err(Error::new(format!(
+                        "cannot call is_some on {} of type {}",
+                        repr,
+                        e.get_type()
+                    ))),
+                }
+            }
+            absy::Expression::UnwrapOr(o, d) => {
+                let (ro, rd) = (o.to_string(), d.to_string());
+                let o = match self.check_expression(*o)? {
+                    TypedExpression::Optional(o) => o,
+                    o => {
+                        return Err(Error::new(format!(
+                            "cannot call unwrap_or on {} of type {}",
+                            ro,
+                            o.get_type()
+                        )))
+                    }
+                };
+                let d = self.check_expression(*d)?;
+
+                let ty = match o.get_type() {
+                    Type::Option(ty) => *ty,
+                    _ => unreachable!(),
+                };
+                if d.get_type() != ty {
+                    return Err(Error::new(format!(
+                        "expected default {} of {} to be of type {}, found {}",
+                        rd,
+                        ro,
+                        ty,
+                        d.get_type()
+                    )));
+                }
+
+                Ok(TypedExpression::unwrap_or(o, d))
+            }
+            absy::Expression::Select(a, i) => {
+                let (ra, ri) = (a.to_string(), i.to_string());
+
+                let a = match self.check_expression(*a)? {
+                    TypedExpression::Array(a) => a,
+                    a => {
+                        return Err(Error::new(format!(
+                            "cannot index into {} of type {}",
+                            ra,
+                            a.get_type()
+                        )))
+                    }
+                };
+                let i = match self.check_expression(*i)? {
+                    TypedExpression::FieldElement(i) => i,
+                    i => {
+                        return Err(Error::new(format!(
+                            "expected index {} to be of type field, found {}",
+                            ri,
+                            i.get_type()
+                        )))
+                    }
+                };
+
+                let (inner, size) = match a.get_type() {
+                    Type::Array(inner, size) => (*inner, size),
+                    _ => unreachable!(),
+                };
+
+                match self.index_range(&i) {
+                    Some((ref min, ref max)) if *max >= BigUint::from(size) => {
+                        return Err(Error::new(match min == max {
+                            true => format!(
+                                "index {} out of bounds for {} of type {}",
+                                max,
+                                ra,
+                                a.get_type()
+                            ),
+                            false => format!(
+                                "index {} reaches {}, out of bounds for {} of type {}",
+                                ri,
+                                max,
+                                ra,
+                                a.get_type()
+                            ),
+                        }))
+                    }
+                    _ => {}
+                }
+
+                Ok(match inner {
+                    Type::FieldElement => TypedExpression::FieldElement(FieldElement::Select(
+                        box a.into_array(),
+                        box i,
+                    )),
+                    Type::Boolean => {
+                        TypedExpression::Boolean(Boolean::Select(box a.into_array(), box i))
+                    }
+                    Type::Uint(..) => {
+                        TypedExpression::Uint(Uint::Select(box a.into_array(), box i))
+                    }
+                    Type::Struct(..) => {
+                        TypedExpression::Structure(Structure::Select(box a.into_array(), box i))
+                    }
+                    Type::Tuple(..) => {
+                        TypedExpression::Tuple(Tuple::Select(box a.into_array(), box i))
+                    }
+                    Type::Enum(..) => TypedExpression::Enumeration(Enumeration::Select(
+                        box a.into_array(),
+                        box i,
+                    )),
+                    Type::Option(..) => {
+                        TypedExpression::Optional(Optional::Select(box a.into_array(), box i))
+                    }
+                    Type::Array(..) => TypedExpression::Array(ArrayExpression::Select(box a, i)),
+                    Type::GenericArray(..) => unreachable!(),
+                })
+            }
+            absy::Expression::IfElse(c, a, b) => {
+                let (rc, ra, rb) = (c.to_string(), a.to_string(), b.to_string());
+                let c = match self.check_expression(*c)? {
+                    TypedExpression::Boolean(c) => c,
+                    c => {
+                        return Err(Error::new(format!(
+                            "expected condition {} to be of type bool, found {}",
+                            rc,
+                            c.get_type()
+                        )))
+                    }
+                };
+
+                let (a, b) = (self.check_expression(*a)?, self.check_expression(*b)?);
+                if a.get_type() != b.get_type() {
+                    return Err(Error::new(format!(
+                        "expected branches {} and {} to be of the same type, found {} and {}",
+                        ra,
+                        rb,
+                        a.get_type(),
+                        b.get_type()
+                    )));
+                }
+
+                Ok(match (a, b) {
+                    (TypedExpression::FieldElement(a), TypedExpression::FieldElement(b)) => {
+                        TypedExpression::FieldElement(FieldElement::IfElse(box c, box a, box b))
+                    }
+                    (TypedExpression::Boolean(a), TypedExpression::Boolean(b)) => {
+                        TypedExpression::Boolean(Boolean::IfElse(box c, box a, box b))
+                    }
+                    (TypedExpression::Uint(a), TypedExpression::Uint(b)) => {
+                        TypedExpression::Uint(Uint::IfElse(box c, box a, box b))
+                    }
+                    (TypedExpression::Array(a), TypedExpression::Array(b)) => {
+                        TypedExpression::Array(ArrayExpression::IfElse(c, box a, box b))
+                    }
+                    (TypedExpression::Structure(a), TypedExpression::Structure(b)) => {
+                        TypedExpression::Structure(Structure::IfElse(box c, box a, box b))
+                    }
+                    (TypedExpression::Tuple(a), TypedExpression::Tuple(b)) => {
+                        TypedExpression::Tuple(Tuple::IfElse(box c, box a, box b))
+                    }
+                    (TypedExpression::Enumeration(a), TypedExpression::Enumeration(b)) => {
+                        TypedExpression::Enumeration(Enumeration::IfElse(box c, box a, box b))
+                    }
+                    (TypedExpression::Optional(a), TypedExpression::Optional(b)) => {
+                        TypedExpression::Optional(Optional::IfElse(box c, box a, box b))
+                    }
+                    _ => unreachable!(),
+                })
+            }
+            absy::Expression::FunctionCall(id, args) => {
+                let position = self.position;
+                let function = match self.functions.get(&id) {
+                    Some((p, signature)) if *p < position => Some(signature.clone()),
+                    _ => None,
+                };
+                let generic = match self.generics.get(&id) {
+                    Some((p, f)) if *p < position => Some((*p, f.clone())),
+                    _ => None,
+                };
+                if function.is_none() && generic.is_none() {
+                    return Err(Error::new(format!("undefined function {}", id)));
+                }
+                if generic.is_some() {
+                    self.called.insert(id.clone());
+                }
+
+                let args = args
+                    .into_iter()
+                    .map(|e| self.check_expression(e))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let types: Vec<_> = args.iter().map(TypedExpression::get_type).collect();
+                let (id, signature) = match generic {
+                    Some((p, f)) => self.instantiate(&id, p, f, &types)?,
+                    None => (id, function.unwrap()),
+                };
+                if types != signature.inputs {
+                    return Err(Error::new(format!(
+                        "expected {} to be called with ({}), found ({})",
+                        id,
+                        type_list(&signature.inputs),
+                        type_list(&types)
+                    )));
+                }
+                if signature.outputs.is_empty() {
+                    return Err(Error::new(format!("expected {} to return a value, found ()", id)));
+                }
+
+                // several values are returned as a tuple
+                let ty = match signature.outputs.len() {
+                    1 => signature.outputs[0].clone(),
+                    _ => Type::Tuple(signature.outputs),
+                };
+
+                let args = args.into_iter().map(|e| e.into_boxed()).collect();
+                Ok(match ty {
+                    Type::FieldElement => {
+                        TypedExpression::FieldElement(FieldElement::FunctionCall(id, args))
+                    }
+                    Type::Boolean => TypedExpression::Boolean(Boolean::FunctionCall(id, args)),
+                    ty @ Type::Uint(..) => TypedExpression::Uint(Uint::FunctionCall(ty, id, args)),
+                    ty @ Type::Array(..) => {
+                        TypedExpression::Array(ArrayExpression::FunctionCall(ty, id, args))
+                    }
+                    ty @ Type::Struct(..) => {
+                        TypedExpression::Structure(Structure::FunctionCall(ty, id, args))
+                    }
+                    ty @ Type::Tuple(..) => {
+                        TypedExpression::Tuple(Tuple::FunctionCall(ty, id, args))
+                    }
+                    ty @ Type::Enum(..) => {
+                        TypedExpression::Enumeration(Enumeration::FunctionCall(ty, id, args))
+                    }
+                    ty @ Type::Option(..) => {
+                        TypedExpression::Optional(Optional::FunctionCall(ty, id, args))
+                    }
+                    Type::GenericArray(..) => unreachable!(),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use absy::Expression::*;
+    use field::{Bls12Field, Bn128Field};
+    use flat_ast::*;
+
+    fn constant(v: u32) -> absy::Expression {
+        FieldConstant(BigUint::from(v))
+    }
+
+    fn field_array(size: usize) -> Type {
+        Type::Array(box Type::FieldElement, size)
+    }
+
+    fn definition(id: &str, ty: Type, e: absy::Expression) -> absy::Statement {
+        absy::Statement::Definition(absy::Variable::new(id, ty), e)
+    }
+
+    #[test]
+    fn nested_array_definition() {
+        // field[2][2] a = [[42, 55], [42, 55]]
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![definition(
+                "a",
+                Type::Array(box field_array(2), 2),
+                ArrayValue(vec![
+                    ArrayValue(vec![constant(42), constant(55)]),
+                    ArrayValue(vec![constant(42), constant(55)]),
+                ]),
+            )],
+        };
+
+        let flattened = Checker::new().check_function::<Bn128Field>(f).unwrap().flatten();
+
+        let vars: Vec<_> = (0..4)
+            .map(|i| FlatVariable::with_name(format!("a_{}", i)))
+            .collect();
+        let values: Vec<_> = vec![42, 55, 42, 55]
+            .into_iter()
+            .map(|v| LinComb::constant(Bn128Field::from(v)))
+            .collect();
+
+        assert_eq!(
+            flattened,
+            FlatFunction::with_statements(
+                vec![FlatStatement::Directive(
+                    vars.clone(),
+                    Helper::Identity,
+                    values.clone(),
+                )].into_iter()
+                .chain(vars.into_iter().zip(values.into_iter()).map(|(var, value)| {
+                    FlatStatement::Assertion(
+                        LinComb::from(var),
+                        LinComb(vec![(Bn128Field::from(1), FlatVariable::one())]),
+                        value,
+                    )
+                })).collect()
+            )
+        );
+    }
+
+    #[test]
+    fn select_and_member() {
+        // {foo: field, bar: bool[2]}[2] a = ...
+        // bool b = a[1].bar[0]
+        let s = Type::Struct(vec![
+            ("foo".to_string(), Type::FieldElement),
+            ("bar".to_string(), Type::Array(box Type::Boolean, 2)),
+        ]);
+        let value = || {
+            StructValue(vec![
+                ("foo".to_string(), constant(42)),
+                (
+                    "bar".to_string(),
+                    ArrayValue(vec![BooleanConstant(true), BooleanConstant(false)]),
+                ),
+            ])
+        };
+
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![
+                definition("a", Type::Array(box s, 2), ArrayValue(vec![value(), value()])),
+                definition(
+                    "b",
+                    Type::Boolean,
+                    Select(
+                        box Member(
+                            box Select(box Identifier("a".to_string()), box constant(1)),
+                            "bar".to_string(),
+                        ),
+                        box constant(0),
+                    ),
+                ),
+            ],
+        };
+
+        let flattened = Checker::new().check_function::<Bn128Field>(f).unwrap().flatten();
+
+        // a[1].bar[0] is the 5th slot of a
+        assert!(flattened.to_string().contains("(1 * b_0) * (1 * ~one) == (1 * a_4)"));
+    }
+
+    #[test]
+    fn bool_in_field_array() {
+        // field[2] a = [42, true]
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![definition(
+                "a",
+                field_array(2),
+                ArrayValue(vec![constant(42), BooleanConstant(true)]),
+            )],
+        };
+
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f).unwrap_err(),
+            vec![Error::new("expected an element of type field, found true of type bool")]
+        );
+    }
+
+    #[test]
+    fn wrong_count_in_def() {
+        // field[2][2] a = [[42], [42, 55]]
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![definition(
+                "a",
+                Type::Array(box field_array(2), 2),
+                ArrayValue(vec![
+                    ArrayValue(vec![constant(42)]),
+                    ArrayValue(vec![constant(42), constant(55)]),
+                ]),
+            )],
+        };
+
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f).unwrap_err(),
+            vec![Error::new(
+                "expected an element of type field[1], found [42, 55] of type field[2]"
+            )]
+        );
+    }
+
+    #[test]
+    fn all_errors_are_reported() {
+        // field[2] a = [42, 55, 66]
+        // field b = c
+        // bool d = a[2]
+        // field e = a.foo
+        // return a + 1
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![
+                definition(
+                    "a",
+                    field_array(2),
+                    ArrayValue(vec![constant(42), constant(55), constant(66)]),
+                ),
+                definition("b", Type::FieldElement, Identifier("c".to_string())),
+                definition(
+                    "d",
+                    Type::Boolean,
+                    Select(box Identifier("a".to_string()), box constant(2)),
+                ),
+                definition(
+                    "e",
+                    Type::FieldElement,
+                    Member(box Identifier("a".to_string()), "foo".to_string()),
+                ),
+                absy::Statement::Return(vec![Add(
+                    box Identifier("a".to_string()),
+                    box constant(1),
+                )]),
+            ],
+        };
+
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f).unwrap_err(),
+            vec![
+                Error::new(
+                    "expected a to be of type field[2], found [42, 55, 66] of type field[3]"
+                ),
+                Error::new("undeclared variable c"),
+                Error::new("index 2 out of bounds for a of type field[2]"),
+                Error::new("a of type field[2] has no member foo"),
+                Error::new("cannot apply + to a of type field[2] and 1 of type field"),
+            ]
+        );
+    }
+
+    #[test]
+    fn arithmetic() {
+        // field a = 42
+        // field b = (a * a - 1) / a**3
+        let a = || box Identifier("a".to_string());
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![
+                definition("a", Type::FieldElement, constant(42)),
+                definition(
+                    "b",
+                    Type::FieldElement,
+                    Div(
+                        box Sub(box Mult(a(), a()), box constant(1)),
+                        box Pow(a(), box constant(3)),
+                    ),
+                ),
+            ],
+        };
+
+        assert!(Checker::new().check_function::<Bn128Field>(f).is_ok());
+    }
+
+    #[test]
+    fn variable_exponent() {
+        // field a = 42
+        // field b = 2**a
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![
+                definition("a", Type::FieldElement, constant(42)),
+                definition(
+                    "b",
+                    Type::FieldElement,
+                    Pow(box constant(2), box Identifier("a".to_string())),
+                ),
+            ],
+        };
+
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f).unwrap_err(),
+            vec![Error::new("expected exponent a to be a constant")]
+        );
+    }
+
+    #[test]
+    fn boolean_operators() {
+        // bool a = true
+        // bool b = !(a xor false) v (a == true)
+        // bool c = a ^ 1
+        let a = || box Identifier("a".to_string());
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![
+                definition("a", Type::Boolean, BooleanConstant(true)),
+                definition(
+                    "b",
+                    Type::Boolean,
+                    Or(
+                        box Not(box Xor(a(), box BooleanConstant(false))),
+                        box Eq(a(), box BooleanConstant(true)),
+                    ),
+                ),
+                definition("c", Type::Boolean, And(a(), box constant(1))),
+            ],
+        };
+
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f).unwrap_err(),
+            vec![Error::new("cannot apply ^ to a of type bool and 1 of type field")]
+        );
+    }
+
+    #[test]
+    fn comparisons() {
+        // field a = 42
+        // bool b = (a < 3) ^ (a != 2) ^ (a >= a)
+        // bool c = b != false
+        // bool d = b == 1
+        let a = || box Identifier("a".to_string());
+        let b = || box Identifier("b".to_string());
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![
+                definition("a", Type::FieldElement, constant(42)),
+                definition(
+                    "b",
+                    Type::Boolean,
+                    And(
+                        box And(
+                            box Lt(a(), box constant(3)),
+                            box Neq(a(), box constant(2)),
+                        ),
+                        box Ge(a(), a()),
+                    ),
+                ),
+                definition("c", Type::Boolean, Neq(b(), box BooleanConstant(false))),
+                definition("d", Type::Boolean, Eq(b(), box constant(1))),
+            ],
+        };
+
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f).unwrap_err(),
+            vec![Error::new("cannot apply == to b of type bool and 1 of type field")]
+        );
+    }
+
+    #[test]
+    fn large_comparison_constants() {
+        // field a = 1
+        // bool b = 2^252 < a
+        // bool c = a <= 2^252
+        // bool d = 2^252 > a
+        // bool e = a >= 2^252
+        // bool f = a < 2^252 - 1
+        let a = || box Identifier("a".to_string());
+        let large = || box FieldConstant(BigUint::from(1u32) << 252);
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![
+                definition("a", Type::FieldElement, constant(1)),
+                definition("b", Type::Boolean, Lt(large(), a())),
+                definition("c", Type::Boolean, Le(a(), large())),
+                definition("d", Type::Boolean, Gt(large(), a())),
+                definition("e", Type::Boolean, Ge(a(), large())),
+                definition(
+                    "f",
+                    Type::Boolean,
+                    Lt(a(), box FieldConstant((BigUint::from(1u32) << 252) - 1u32)),
+                ),
+            ],
+        };
+
+        let large = BigUint::from(1u32) << 252;
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f).unwrap_err(),
+            vec!["<", "<=", ">", ">="]
+                .into_iter()
+                .map(|operator| Error::new(format!(
+                    "cannot apply {} to {}, which does not fit in 252 bits",
+                    operator, large
+                ))).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn folded_comparison_constants() {
+        // def main(field a) {
+        //   bool b = 0 - 1 < a
+        //   for field i in 0..2 { bool c = a > i - 2 }
+        // }
+        let a = || box Identifier("a".to_string());
+        let main = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![absy::Parameter::new(
+                absy::Variable::new("a", Type::FieldElement),
+                false,
+            )],
+            returns: vec![],
+            statements: vec![
+                definition("b", Type::Boolean, Lt(box Sub(box constant(0), box constant(1)), a())),
+                absy::Statement::For(
+                    absy::Variable::new("i", Type::FieldElement),
+                    absy::Size::Constant(0),
+                    absy::Size::Constant(2),
+                    vec![definition(
+                        "c",
+                        Type::Boolean,
+                        Gt(a(), box Sub(box Identifier("i".to_string()), box constant(2))),
+                    )],
+                ),
+            ],
+        };
+        let p = absy::Program {
+            functions: vec![main],
+        };
+
+        // the constants are only known once folded, and the loop unrolled
+        assert_eq!(
+            Checker::new().check_program::<Bn128Field>(p).unwrap_err(),
+            vec![
+                Error::new("cannot compare -1, which does not fit in 252 bits"),
+                Error::new("cannot compare -2, which does not fit in 252 bits"),
+            ]
+        );
+    }
+
+    #[test]
+    fn structural_equality() {
+        let s = Type::Struct(vec![
+            ("x".to_string(), Type::FieldElement),
+            ("y".to_string(), Type::Boolean),
+        ]);
+        let a = || box Identifier("a".to_string());
+        let b = || box Identifier("b".to_string());
+        let array = |values: Vec<u32>| ArrayValue(values.into_iter().map(constant).collect());
+
+        // def main(field[2] a, {x: field, y: bool} b) -> (bool) {
+        //   assert_eq(a, [1, 2])
+        //   assert_eq(b, {x: 3, y: true})
+        //   return (a != [2, 1]) ^ (b == b)
+        // }
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![
+                absy::Parameter::new(absy::Variable::new("a", field_array(2)), false),
+                absy::Parameter::new(absy::Variable::new("b", s.clone()), false),
+            ],
+            returns: vec![Type::Boolean],
+            statements: vec![
+                absy::Statement::AssertEq(*a(), array(vec![1, 2])),
+                absy::Statement::AssertEq(
+                    *b(),
+                    StructValue(vec![
+                        ("x".to_string(), constant(3)),
+                        ("y".to_string(), BooleanConstant(true)),
+                    ]),
+                ),
+                absy::Statement::Return(vec![And(
+                    box Neq(a(), box array(vec![2, 1])),
+                    box Eq(b(), b()),
+                )]),
+            ],
+        };
+
+        let flattened = Checker::new().check_function::<Bn128Field>(f).unwrap().flatten();
+
+        assert!(flattened.to_string().contains("(1 * a_1) * (1 * ~one) == (2 * ~one)"));
+        assert!(flattened.to_string().contains("(1 * b_1) * (1 * ~one) == (1 * ~one)"));
+
+        // bool c = a == [1, 2, 3]
+        // bool d = b != a
+        // assert_eq(a, 1)
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![
+                absy::Parameter::new(absy::Variable::new("a", field_array(2)), false),
+                absy::Parameter::new(absy::Variable::new("b", s), false),
+            ],
+            returns: vec![],
+            statements: vec![
+                definition("c", Type::Boolean, Eq(a(), box array(vec![1, 2, 3]))),
+                definition("d", Type::Boolean, Neq(b(), a())),
+                absy::Statement::AssertEq(*a(), constant(1)),
+            ],
+        };
+
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f).unwrap_err(),
+            vec![
+                Error::new("cannot apply == to a of type field[2] and [1, 2, 3] of type field[3]"),
+                Error::new(
+                    "cannot apply != to b of type {x: field, y: bool} and a of type field[2]"
+                ),
+                Error::new("expected a and 1 to be of the same type, found field[2] and field"),
+            ]
+        );
+    }
+
+    #[test]
+    fn if_else() {
+        // field[2] a = if true then [1, 2] else [3, 4]
+        // field b = if a[0] then 1 else 2
+        // field c = if true then a else 1
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![
+                definition(
+                    "a",
+                    field_array(2),
+                    IfElse(
+                        box BooleanConstant(true),
+                        box ArrayValue(vec![constant(1), constant(2)]),
+                        box ArrayValue(vec![constant(3), constant(4)]),
+                    ),
+                ),
+                definition(
+                    "b",
+                    Type::FieldElement,
+                    IfElse(
+                        box Select(box Identifier("a".to_string()), box constant(0)),
+                        box constant(1),
+                        box constant(2),
+                    ),
+                ),
+                definition(
+                    "c",
+                    Type::FieldElement,
+                    IfElse(
+                        box BooleanConstant(true),
+                        box Identifier("a".to_string()),
+                        box constant(1),
+                    ),
+                ),
+            ],
+        };
+
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f).unwrap_err(),
+            vec![
+                Error::new("expected condition a[0] to be of type bool, found field"),
+                Error::new(
+                    "expected branches a and 1 to be of the same type, found field[2] and field"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parameters() {
+        // def main(private field[2] a, bool b) -> (field) {
+        //   field c = a[1]
+        //   return c
+        // }
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![
+                absy::Parameter::new(absy::Variable::new("a", field_array(2)), true),
+                absy::Parameter::new(absy::Variable::new("b", Type::Boolean), false),
+            ],
+            returns: vec![Type::FieldElement],
+            statements: vec![
+                definition(
+                    "c",
+                    Type::FieldElement,
+                    Select(box Identifier("a".to_string()), box constant(1)),
+                ),
+                absy::Statement::Return(vec![Identifier("c".to_string())]),
+            ],
+        };
+
+        let flattened = Checker::new().check_function::<Bn128Field>(f).unwrap().flatten();
+
+        assert_eq!(flattened.public_inputs(), &vec![FlatVariable::with_name("b_0")]);
+        assert_eq!(
+            flattened.private_inputs(),
+            &vec![FlatVariable::with_name("a_0"), FlatVariable::with_name("a_1")]
+        );
+        assert_eq!(flattened.outputs(), &vec![FlatVariable::output(0)]);
+        assert_eq!(
+            flattened.to_string().lines().next(),
+            Some("(1 * b_0) * (1 * ~one + -1 * b_0) == (0 * ~one)")
+        );
+    }
+
+    fn square() -> absy::Function {
+        // def square(field x) -> (field) { return x * x }
+        absy::Function {
+            id: "square".to_string(),
+            generics: vec![],
+            arguments: vec![absy::Parameter::new(
+                absy::Variable::new("x", Type::FieldElement),
+                false,
+            )],
+            returns: vec![Type::FieldElement],
+            statements: vec![absy::Statement::Return(vec![Mult(
+                box Identifier("x".to_string()),
+                box Identifier("x".to_string()),
+            )])],
+        }
+    }
+
+    #[test]
+    fn program() {
+        // def main(private field a) -> (field) {
+        //   field b = square(a)
+        //   return square(b)
+        // }
+        let main = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![absy::Parameter::new(
+                absy::Variable::new("a", Type::FieldElement),
+                true,
+            )],
+            returns: vec![Type::FieldElement],
+            statements: vec![
+                definition(
+                    "b",
+                    Type::FieldElement,
+                    FunctionCall("square".to_string(), vec![Identifier("a".to_string())]),
+                ),
+                absy::Statement::Return(vec![FunctionCall(
+                    "square".to_string(),
+                    vec![Identifier("b".to_string())],
+                )]),
+            ],
+        };
+        let p = absy::Program {
+            functions: vec![square(), main],
+        };
+
+        let flattened = Checker::new().check_program::<Bn128Field>(p).unwrap().flatten();
+
+        let main = flattened.main().unwrap();
+        assert_eq!(main.private_inputs(), &vec![FlatVariable::with_name("a_0")]);
+        assert_eq!(main.outputs(), &vec![FlatVariable::output(0)]);
+        assert!(main.to_string().contains("square~1.x_0"));
+        assert_eq!(
+            flattened.function("square").unwrap().public_inputs(),
+            &vec![FlatVariable::with_name("x_0")]
+        );
+    }
+
+    #[test]
+    fn call_errors() {
+        // def main() -> (bool) {
+        //   field a = square(true)
+        //   field b = main()
+        //   return 1
+        // }
+        let main = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![Type::Boolean],
+            statements: vec![
+                definition(
+                    "a",
+                    Type::FieldElement,
+                    FunctionCall("square".to_string(), vec![BooleanConstant(true)]),
+                ),
+                definition("b", Type::FieldElement, FunctionCall("main".to_string(), vec![])),
+                absy::Statement::Return(vec![constant(1)]),
+            ],
+        };
+        let p = absy::Program {
+            functions: vec![square(), main, square()],
+        };
+
+        assert_eq!(
+            Checker::new().check_program::<Bn128Field>(p).unwrap_err(),
+            vec![
+                Error::new("expected square to be called with (field), found (bool)"),
+                Error::new("undefined function main"),
+                Error::new("expected to return (bool), found (field)"),
+                Error::new("duplicate function square"),
+            ]
+        );
+
+        let p = absy::Program {
+            functions: vec![square()],
+        };
+
+        assert_eq!(
+            Checker::new().check_program::<Bn128Field>(p).unwrap_err(),
+            vec![Error::new("no main function")]
+        );
+    }
+
+    #[test]
+    fn missing_return() {
+        // def zero() -> (field) { field a = 0 }
+        let zero = absy::Function {
+            id: "zero".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![Type::FieldElement],
+            statements: vec![definition("a", Type::FieldElement, constant(0))],
+        };
+
+        // def one() -> (field) {
+        //   return 0
+        //   return 1
+        // }
+        let one = absy::Function {
+            id: "one".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![Type::FieldElement],
+            statements: vec![
+                absy::Statement::Return(vec![constant(0)]),
+                absy::Statement::Return(vec![constant(1)]),
+            ],
+        };
+
+        // def main() -> (field) {
+        //   field b = zero() + one()
+        //   for field i in 0..1 { return b }
+        // }
+        let main = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![Type::FieldElement],
+            statements: vec![
+                definition(
+                    "b",
+                    Type::FieldElement,
+                    Add(
+                        box FunctionCall("zero".to_string(), vec![]),
+                        box FunctionCall("one".to_string(), vec![]),
+                    ),
+                ),
+                absy::Statement::For(
+                    absy::Variable::new("i", Type::FieldElement),
+                    absy::Size::Constant(0),
+                    absy::Size::Constant(1),
+                    vec![absy::Statement::Return(vec![Identifier("b".to_string())])],
+                ),
+            ],
+        };
+        let p = absy::Program {
+            functions: vec![zero, one, main],
+        };
+
+        assert_eq!(
+            Checker::new().check_program::<Bn128Field>(p).unwrap_err(),
+            vec![
+                Error::new("expected to return (field), found no return statement"),
+                Error::new("expected return to be the last statement of the function"),
+                Error::new("expected to return (field), found no return statement"),
+                Error::new("expected return to be the last statement of the function"),
+            ]
+        );
+    }
+
+    #[test]
+    fn generics() {
+        let xs = || Type::GenericArray(box Type::FieldElement, "N".to_string());
+        // def sum<N>(field[N] xs) -> (field) {
+        //   field s = 0
+        //   for field i in 0..N { s = s + xs[i] }
+        //   return s
+        // }
+        let sum = || absy::Function {
+            id: "sum".to_string(),
+            generics: vec!["N".to_string()],
+            arguments: vec![absy::Parameter::new(absy::Variable::new("xs", xs()), false)],
+            returns: vec![Type::FieldElement],
+            statements: vec![
+                definition("s", Type::FieldElement, constant(0)),
+                absy::Statement::For(
+                    absy::Variable::new("i", Type::FieldElement),
+                    absy::Size::Constant(0),
+                    absy::Size::Generic("N".to_string()),
+                    vec![absy::Statement::Assignment(
+                        "s".to_string(),
+                        Add(
+                            box Identifier("s".to_string()),
+                            box Select(
+                                box Identifier("xs".to_string()),
+                                box Identifier("i".to_string()),
+                            ),
+                        ),
+                    )],
+                ),
+                absy::Statement::Return(vec![Identifier("s".to_string())]),
+            ],
+        };
+        let call = |id: &str, args: Vec<&str>| {
+            FunctionCall(
+                id.to_string(),
+                args.into_iter().map(|a| Identifier(a.to_string())).collect(),
+            )
+        };
+
+        // def main(private field[3] a, field[2] b) -> (field) {
+        //   return sum(a) + sum(b) + sum(a)
+        // }
+        let main = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![
+                absy::Parameter::new(absy::Variable::new("a", field_array(3)), true),
+                absy::Parameter::new(absy::Variable::new("b", field_array(2)), false),
+            ],
+            returns: vec![Type::FieldElement],
+            statements: vec![absy::Statement::Return(vec![Add(
+                box Add(box call("sum", vec!["a"]), box call("sum", vec!["b"])),
+                box call("sum", vec!["a"]),
+            )])],
+        };
+        let p = absy::Program {
+            functions: vec![sum(), main],
+        };
+
+        let flattened = Checker::new().check_program::<Bn128Field>(p).unwrap().flatten();
+
+        assert_eq!(
+            flattened.function("sum<3>").unwrap().public_inputs(),
+            &vec![
+                FlatVariable::with_name("xs_0"),
+                FlatVariable::with_name("xs_1"),
+                FlatVariable::with_name("xs_2"),
+            ]
+        );
+        assert_eq!(flattened.function("sum<2>").unwrap().public_inputs().len(), 2);
+        let main = flattened.main().unwrap();
+        assert!(main.to_string().contains("sum<3>~0.xs_2"));
+        assert!(main.to_string().contains("sum<2>~1.xs_1"));
+        assert!(main.to_string().contains("sum<3>~2.xs_0"));
+
+        // def pair<N>(field[N] a, field[N] b) -> (field[M]) { return a }
+        // def zeros<N>() -> (field[N]) { return zeros() }
+        // def main(field[2] a, field[3] b) {
+        //   field c = sum(true)
+        //   field[2] d = pair(a, b)
+        //   field[2] e = pair(a, a)
+        //   field[2] f = zeros()
+        //   field[N] g = a
+        // }
+        let pair = absy::Function {
+            id: "pair".to_string(),
+            generics: vec!["N".to_string()],
+            arguments: vec![
+                absy::Parameter::new(absy::Variable::new("a", xs()), false),
+                absy::Parameter::new(absy::Variable::new("b", xs()), false),
+            ],
+            returns: vec![Type::GenericArray(box Type::FieldElement, "M".to_string())],
+            statements: vec![absy::Statement::Return(vec![Identifier("a".to_string())])],
+        };
+        let zeros = absy::Function {
+            id: "zeros".to_string(),
+            generics: vec!["N".to_string()],
+            arguments: vec![],
+            returns: vec![xs()],
+            statements: vec![absy::Statement::Return(vec![call("zeros", vec![])])],
+        };
+        let main = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![
+                absy::Parameter::new(absy::Variable::new("a", field_array(2)), false),
+                absy::Parameter::new(absy::Variable::new("b", field_array(3)), false),
+            ],
+            returns: vec![],
+            statements: vec![
+                definition(
+                    "c",
+                    Type::FieldElement,
+                    FunctionCall("sum".to_string(), vec![BooleanConstant(true)]),
+                ),
+                definition("d", field_array(2), call("pair", vec!["a", "b"])),
+                definition("e", field_array(2), call("pair", vec!["a", "a"])),
+                definition("f", field_array(2), call("zeros", vec![])),
+                definition("g", xs(), Identifier("a".to_string())),
+            ],
+        };
+        let p = absy::Program {
+            functions: vec![sum(), pair, zeros, main],
+        };
+
+        assert_eq!(
+            Checker::new().check_program::<Bn128Field>(p).unwrap_err(),
+            vec![
+                Error::new("expected sum to be called with (field[N]), found (bool)"),
+                Error::new(
+                    "expected pair to be called with (field[N], field[N]), found (field[2], field[3])"
+                ),
+                Error::new("undeclared size M"),
+                Error::new("cannot infer size N of zeros"),
+                Error::new("undeclared size N"),
+            ]
+        );
+
+        // def broken<N>(field[N] xs) -> (field) { return true }
+        // def main() {}
+        let broken = absy::Function {
+            id: "broken".to_string(),
+            generics: vec!["N".to_string()],
+            arguments: vec![absy::Parameter::new(absy::Variable::new("xs", xs()), false)],
+            returns: vec![Type::FieldElement],
+            statements: vec![absy::Statement::Return(vec![BooleanConstant(true)])],
+        };
+        let main = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![],
+        };
+        let p = absy::Program {
+            functions: vec![broken, main],
+        };
+
+        // the body of a generic function is only checked for its instances, so it must have one
+        assert_eq!(
+            Checker::new().check_program::<Bn128Field>(p).unwrap_err(),
+            vec![Error::new("generic function broken is never called")]
+        );
+    }
+
+    #[test]
+    fn tuples() {
+        // def pair(field x) -> (field, bool) { return x, true }
+        let pair = || absy::Function {
+            id: "pair".to_string(),
+            generics: vec![],
+            arguments: vec![absy::Parameter::new(
+                absy::Variable::new("x", Type::FieldElement),
+                false,
+            )],
+            returns: vec![Type::FieldElement, Type::Boolean],
+            statements: vec![absy::Statement::Return(vec![
+                Identifier("x".to_string()),
+                BooleanConstant(true),
+            ])],
+        };
+        let call = |v| FunctionCall("pair".to_string(), vec![constant(v)]);
+
+        // def main() -> (bool) {
+        //   field a, bool b = pair(1)
+        //   (field, bool) t = (a, pair(2).1)
+        //   return t.1
+        // }
+        let main = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![Type::Boolean],
+            statements: vec![
+                absy::Statement::MultipleDefinition(
+                    vec![
+                        absy::Variable::new("a", Type::FieldElement),
+                        absy::Variable::new("b", Type::Boolean),
+                    ],
+                    call(1),
+                ),
+                definition(
+                    "t",
+                    Type::Tuple(vec![Type::FieldElement, Type::Boolean]),
+                    TupleValue(vec![Identifier("a".to_string()), Element(box call(2), 1)]),
+                ),
+                absy::Statement::Return(vec![Element(box Identifier("t".to_string()), 1)]),
+            ],
+        };
+        let p = absy::Program {
+            functions: vec![pair(), main],
+        };
+
+        let flattened = Checker::new().check_program::<Bn128Field>(p).unwrap().flatten();
+
+        let main = flattened.main().unwrap();
+        assert_eq!(main.outputs(), &vec![FlatVariable::output(0)]);
+        assert!(main.to_string().contains("# a_0 := Identity(1 * pair~0.x_0)"));
+        assert!(main.to_string().contains("# t_0, t_1 := Identity(1 * a_0, 1 * ~one)"));
+
+        // def main() {
+        //   field a, field b = pair(1)
+        //   field c = pair(2).2
+        //   field d = c.0
+        // }
+        let main = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![
+                absy::Statement::MultipleDefinition(
+                    vec![
+                        absy::Variable::new("a", Type::FieldElement),
+                        absy::Variable::new("b", Type::FieldElement),
+                    ],
+                    call(1),
+                ),
+                definition("c", Type::FieldElement, Element(box call(2), 2)),
+                definition("d", Type::FieldElement, Element(box Identifier("c".to_string()), 0)),
+            ],
+        };
+        let p = absy::Program {
+            functions: vec![pair(), main],
+        };
+
+        assert_eq!(
+            Checker::new().check_program::<Bn128Field>(p).unwrap_err(),
+            vec![
+                Error::new(
+                    "expected a, b to be of type (field, field), found pair(1) of type (field, bool)"
+                ),
+                Error::new("pair(2) of type (field, bool) has no element 2"),
+                Error::new("c of type field has no element 0"),
+            ]
+        );
+    }
+
+    #[test]
+    fn enums() {
+        // enum Op { Add(field), Swap(field[2]) }
+        let op = Type::Enum(
+            "Op".to_string(),
+            vec![
+                ("Add".to_string(), Type::FieldElement),
+                ("Swap".to_string(), field_array(2)),
+            ],
+        );
+        let e = || box Identifier("e".to_string());
+        let arm = |variant: &str, binding: &str, e| (variant.to_string(), binding.to_string(), e);
+
+        // def main(private Op e) -> (field) {
+        //   Op f = Op::Add(1)
+        //   return match e { Add(a) => a, Swap(b) => b[0] }
+        // }
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![absy::Parameter::new(absy::Variable::new("e", op.clone()), true)],
+            returns: vec![Type::FieldElement],
+            statements: vec![
+                definition(
+                    "f",
+                    op.clone(),
+                    EnumValue(op.clone(), "Add".to_string(), box constant(1)),
+                ),
+                absy::Statement::Return(vec![Match(
+                    e(),
+                    vec![
+                        arm("Add", "a", Identifier("a".to_string())),
+                        arm(
+                            "Swap",
+                            "b",
+                            Select(box Identifier("b".to_string()), box constant(0)),
+                        ),
+                    ],
+                )]),
+            ],
+        };
+
+        let flattened = Checker::new().check_function::<Bn128Field>(f).unwrap().flatten();
+
+        assert_eq!(
+            flattened.private_inputs(),
+            &vec![
+                FlatVariable::with_name("e_0"),
+                FlatVariable::with_name("e_1"),
+                FlatVariable::with_name("e_2"),
+            ]
+        );
+        // the unused slot of the payload is zero
+        assert!(flattened
+            .to_string()
+            .contains("# f_0, f_1, f_2 := Identity(0 * ~one, 1 * ~one, 0 * ~one)"));
+
+        // def main(private Op e) {
+        //   Op f = Op::Add(true)
+        //   field g = match e { Mul(a) => a }
+        //   field h = match e { Add(a) => a }
+        //   field i = match e { Add(a) => a, Swap(b) => b }
+        //   field j = a
+        // }
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![absy::Parameter::new(absy::Variable::new("e", op.clone()), true)],
+            returns: vec![],
+            statements: vec![
+                definition(
+                    "f",
+                    op.clone(),
+                    EnumValue(op.clone(), "Add".to_string(), box BooleanConstant(true)),
+                ),
+                definition(
+                    "g",
+                    Type::FieldElement,
+                    Match(e(), vec![arm("Mul", "a", Identifier("a".to_string()))]),
+                ),
+                definition(
+                    "h",
+                    Type::FieldElement,
+                    Match(e(), vec![arm("Add", "a", Identifier("a".to_string()))]),
+                ),
+                definition(
+                    "i",
+                    Type::FieldElement,
+                    Match(
+                        e(),
+                        vec![
+                            arm("Add", "a", Identifier("a".to_string())),
+                            arm("Swap", "b", Identifier("b".to_string())),
+                        ],
+                    ),
+                ),
+                definition("j", Type::FieldElement, Identifier("a".to_string())),
+            ],
+        };
+
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f).unwrap_err(),
+            vec![
+                Error::new(
+                    "expected Op::Add to hold a value of type field, found true of type bool"
+                ),
+                Error::new("Op has no variant Mul"),
+                Error::new("match on e does not cover Swap"),
+                Error::new(
+                    "expected the arms of the match on e to be of the same type, found field and \
+                     field[2]"
+                ),
+                Error::new("undeclared variable a"),
+            ]
+        );
+
+        // enum Empty {}
+        // enum Twice { A(field), A(bool) }
+        // def main(Empty a, (field, Twice) b) {}
+        let twice = Type::Enum(
+            "Twice".to_string(),
+            vec![
+                ("A".to_string(), Type::FieldElement),
+                ("A".to_string(), Type::Boolean),
+            ],
+        );
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![
+                absy::Parameter::new(
+                    absy::Variable::new("a", Type::Enum("Empty".to_string(), vec![])),
+                    false,
+                ),
+                absy::Parameter::new(
+                    absy::Variable::new("b", Type::Tuple(vec![Type::FieldElement, twice])),
+                    false,
+                ),
+            ],
+            returns: vec![],
+            statements: vec![],
+        };
+
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f).unwrap_err(),
+            vec![
+                Error::new("Empty has no variants"),
+                Error::new("duplicate variant A in Twice"),
+            ]
+        );
+    }
+
+    #[test]
+    fn options() {
+        let option = |ty| Type::Option(box ty);
+        let a = || box Identifier("a".to_string());
+
+        // def main(private Option<field> a) -> (field) {
+        //   Option<field> b = Some(1)
+        //   bool c = a.is_some()
+        //   return a.unwrap_or(2)
+        // }
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![absy::Parameter::new(
+                absy::Variable::new("a", option(Type::FieldElement)),
+                true,
+            )],
+            returns: vec![Type::FieldElement],
+            statements: vec![
+                definition("b", option(Type::FieldElement), SomeValue(box constant(1))),
+                definition("c", Type::Boolean, IsSome(a())),
+                absy::Statement::Return(vec![UnwrapOr(a(), box constant(2))]),
+            ],
+        };
+
+        let flattened = Checker::new().check_function::<Bn128Field>(f).unwrap().flatten();
+
+        assert_eq!(
+            flattened.to_string().lines().take(2).collect::<Vec<_>>(),
+            vec![
+                "(1 * a_0) * (1 * ~one + -1 * a_0) == (0 * ~one)",
+                "(1 * ~one + -1 * a_0) * (1 * a_1) == (0 * ~one)",
+            ]
+        );
+
+        // def main(private Option<field> a) {
+        //   Option<bool> b = None::<field>
+        //   bool c = b.is_some().is_some()
+        //   field d = a.unwrap_or(true)
+        // }
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![absy::Parameter::new(
+                absy::Variable::new("a", option(Type::FieldElement)),
+                true,
+            )],
+            returns: vec![],
+            statements: vec![
+                definition("b", option(Type::Boolean), NoneValue(Type::FieldElement)),
+                definition(
+                    "c",
+                    Type::Boolean,
+                    IsSome(box IsSome(box Identifier("b".to_string()))),
+                ),
+                definition("d", Type::FieldElement, UnwrapOr(a(), box BooleanConstant(true))),
+            ],
+        };
+
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f).unwrap_err(),
+            vec![
+                Error::new(
+                    "expected b to be of type Option<bool>, found None of type Option<field>"
+                ),
+                Error::new("cannot call is_some on b.is_some() of type bool"),
+                Error::new("expected default true of a to be of type field, found bool"),
+            ]
+        );
+    }
+
+    #[test]
+    fn for_loop() {
+        // field[3] a = [1, 2, 3]
+        // for field i in 0..3 {
+        //   field b = a[i]
+        //   bool c = i
+        // }
+        // field d = i
+        // for bool j in 0..3 {}
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![
+                definition(
+                    "a",
+                    field_array(3),
+                    ArrayValue(vec![constant(1), constant(2), constant(3)]),
+                ),
+                absy::Statement::For(
+                    absy::Variable::new("i", Type::FieldElement),
+                    absy::Size::Constant(0),
+                    absy::Size::Constant(3),
+                    vec![
+                        definition(
+                            "b",
+                            Type::FieldElement,
+                            Select(
+                                box Identifier("a".to_string()),
+                                box Identifier("i".to_string()),
+                            ),
+                        ),
+                        definition("c", Type::Boolean, Identifier("i".to_string())),
+                    ],
+                ),
+                definition("d", Type::FieldElement, Identifier("i".to_string())),
+                absy::Statement::For(
+                    absy::Variable::new("j", Type::Boolean),
+                    absy::Size::Constant(0),
+                    absy::Size::Constant(3),
+                    vec![],
+                ),
+            ],
+        };
+
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f).unwrap_err(),
+            vec![
+                Error::new("expected c to be of type bool, found i of type field"),
+                Error::new("undeclared variable i"),
+                Error::new("expected loop variable j to be of type field, found bool"),
+            ]
+        );
+    }
+
+    #[test]
+    fn folded_index_bounds() {
+        // def main(field[2] a) -> (field) {
+        //   return a[if true then 2 else 0]
+        // }
+        let main = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![absy::Parameter::new(absy::Variable::new("a", field_array(2)), false)],
+            returns: vec![Type::FieldElement],
+            statements: vec![absy::Statement::Return(vec![Select(
+                box Identifier("a".to_string()),
+                box IfElse(box BooleanConstant(true), box constant(2), box constant(0)),
+            )])],
+        };
+        let p = absy::Program {
+            functions: vec![main],
+        };
+
+        // the index is only known once folded
+        assert_eq!(
+            Checker::new().check_program::<Bn128Field>(p).unwrap_err(),
+            vec![Error::new("index 2 out of bounds for a of type field[2]")]
+        );
+    }
+
+    #[test]
+    fn loop_index_bounds() {
+        let i = || box Identifier("i".to_string());
+        let select = |index| {
+            definition(
+                "b",
+                Type::FieldElement,
+                Select(box Identifier("a".to_string()), box index),
+            )
+        };
+        let for_loop = |end, statements| {
+            absy::Statement::For(
+                absy::Variable::new("i", Type::FieldElement),
+                absy::Size::Constant(0),
+                absy::Size::Constant(end),
+                statements,
+            )
+        };
+
+        // field[2] a = [1, 2]
+        // for field i in 0..2 { field b = a[1 - i] }
+        // for field i in 0..3 { field b = a[i] }
+        // for field i in 0..2 { field b = a[i * 2] }
+        // for field i in 0..2 { field b = a[i - 1] }
+        // for field i in 0..0 { field b = a[i + 5] }
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![
+                definition("a", field_array(2), ArrayValue(vec![constant(1), constant(2)])),
+                for_loop(2, vec![select(Sub(box constant(1), i()))]),
+                for_loop(3, vec![select(*i())]),
+                for_loop(2, vec![select(Mult(i(), box constant(2)))]),
+                for_loop(2, vec![select(Sub(i(), box constant(1)))]),
+                for_loop(0, vec![select(Add(i(), box constant(5)))]),
+            ],
+        };
+
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f).unwrap_err(),
+            vec![
+                Error::new("index i reaches 2, out of bounds for a of type field[2]"),
+                Error::new("index i * 2 reaches 2, out of bounds for a of type field[2]"),
+                Error::new(format!(
+                    "index i - 1 reaches {}, out of bounds for a of type field[2]",
+                    Bn128Field::modulus() - 1u32
+                )),
+            ]
+        );
+
+        // def main() {
+        //   field[2] a = [1, 2]
+        //   for field i in 0..3 { field b = a[if i == 1 then 0 else i] }
+        // }
+        let main = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![
+                definition("a", field_array(2), ArrayValue(vec![constant(1), constant(2)])),
+                for_loop(
+                    3,
+                    vec![select(IfElse(box Eq(i(), box constant(1)), box constant(0), i()))],
+                ),
+            ],
+        };
+        let p = absy::Program {
+            functions: vec![main],
+        };
+
+        // indices the checker cannot bound are reported once the loop is unrolled
+        assert_eq!(
+            Checker::new().check_program::<Bn128Field>(p).unwrap_err(),
+            vec![Error::new("index 2 out of bounds for a of type field[2]")]
+        );
+    }
+
+    #[test]
+    fn assignment() {
+        // field a = 1
+        // a = a + 1
+        // a = true
+        // b = 1
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![
+                definition("a", Type::FieldElement, constant(1)),
+                absy::Statement::Assignment(
+                    "a".to_string(),
+                    Add(box Identifier("a".to_string()), box constant(1)),
+                ),
+                absy::Statement::Assignment("a".to_string(), BooleanConstant(true)),
+                absy::Statement::Assignment("b".to_string(), constant(1)),
+            ],
+        };
+
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f).unwrap_err(),
+            vec![
+                Error::new("expected a to be of type field, found true of type bool"),
+                Error::new("undeclared variable b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn scopes() {
+        // field a = 1
+        // field a = 2
+        // for field i in 0..2 {
+        //   field a = i
+        //   field b = a
+        //   i = 1
+        // }
+        // field c = b
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![
+                definition("a", Type::FieldElement, constant(1)),
+                definition("a", Type::FieldElement, constant(2)),
+                absy::Statement::For(
+                    absy::Variable::new("i", Type::FieldElement),
+                    absy::Size::Constant(0),
+                    absy::Size::Constant(2),
+                    vec![
+                        definition("a", Type::FieldElement, Identifier("i".to_string())),
+                        definition("b", Type::FieldElement, Identifier("a".to_string())),
+                        absy::Statement::Assignment("i".to_string(), constant(1)),
+                    ],
+                ),
+                definition("c", Type::FieldElement, Identifier("b".to_string())),
+            ],
+        };
+
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f).unwrap_err(),
+            vec![
+                Error::new("a is already declared in this scope"),
+                Error::new("cannot assign to immutable variable i"),
+                Error::new("undeclared variable b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn field_constants() {
+        // field a = p, where p is the modulus of BN254, which is smaller than that of BLS12-381
+        let f = || absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![definition(
+                "a",
+                Type::FieldElement,
+                FieldConstant(Bn128Field::modulus()),
+            )],
+        };
+
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f()).unwrap_err(),
+            vec![Error::new(format!(
+                "constant {0} does not fit in a field of modulus {0}",
+                Bn128Field::modulus()
+            ))]
+        );
+
+        let flattened = Checker::new()
+            .check_function::<Bls12Field>(f())
+            .unwrap()
+            .flatten();
+        assert_eq!(
+            flattened.to_string().lines().nth(1),
+            Some(
+                format!(
+                    "(1 * a_0) * (1 * ~one) == ({} * ~one)",
+                    Bn128Field::modulus()
+                ).as_str()
+            )
+        );
+    }
+
+    #[test]
+    fn uints() {
+        // def main(u8 a, u16 b) -> (u8) {
+        //   u8 c = !(a xor 15_u8) << 1
+        //   return c * a
+        // }
+        let a = || box Identifier("a".to_string());
+        let parameters = vec![
+            absy::Parameter::new(absy::Variable::new("a", Type::Uint(8)), false),
+            absy::Parameter::new(absy::Variable::new("b", Type::Uint(16)), false),
+        ];
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: parameters.clone(),
+            returns: vec![Type::Uint(8)],
+            statements: vec![
+                definition(
+                    "c",
+                    Type::Uint(8),
+                    LeftShift(
+                        box Not(box Xor(a(), box UintConstant(15, 8))),
+                        box constant(1),
+                    ),
+                ),
+                absy::Statement::Return(vec![Mult(box Identifier("c".to_string()), a())]),
+            ],
+        };
+
+        let flattened = Checker::new().check_function::<Bn128Field>(f).unwrap().flatten();
+
+        // the inputs are range checked
+        assert_eq!(
+            flattened.to_string().lines().next(),
+            Some("# ~0, ~1, ~2, ~3, ~4, ~5, ~6, ~7 := Bits(1 * a_0)")
+        );
+
+        // def main(u8 a, u16 b) -> (u8) {
+        //   u8 c = a + b
+        //   u8 d = 256_u8
+        //   u8 e = a << b
+        //   u8 f = a / a
+        //   u12 g = 1_u12
+        //   u8 h = 1_u300
+        //   return a
+        // }
+        let f = absy::Function {
+            id: "main".to_string(),
+            generics: vec![],
+            arguments: parameters,
+            returns: vec![Type::Uint(8)],
+            statements: vec![
+                definition("c", Type::Uint(8), Add(a(), box Identifier("b".to_string()))),
+                definition("d", Type::Uint(8), UintConstant(256, 8)),
+                definition("e", Type::Uint(8), LeftShift(a(), box Identifier("b".to_string()))),
+                definition("i", Type::Uint(8), RightShift(a(), box FieldConstant(8u32.into()))),
+                definition(
+                    "j",
+                    Type::Uint(8),
+                    LeftShift(a(), box FieldConstant(BigUint::from(1u32) << 40)),
+                ),
+                definition("f", Type::Uint(8), Div(a(), a())),
+                definition("g", Type::Uint(12), UintConstant(1, 12)),
+                definition("h", Type::Uint(8), UintConstant(1, 300)),
+                absy::Statement::Return(vec![Identifier("a".to_string())]),
+            ],
+        };
+
+        assert_eq!(
+            Checker::new().check_function::<Bn128Field>(f).unwrap_err(),
+            vec![
+                Error::new("cannot apply + to a of type u8 and b of type u16"),
+                Error::new("constant 256 does not fit in u8"),
+                Error::new("expected shift amount b to be a constant"),
+                Error::new("shift amount 8 is out of range for a of type u8"),
+                Error::new("shift amount 1099511627776 is out of range for a of type u8"),
+                Error::new("cannot apply / to a of type u8 and a of type u8"),
+                Error::new("unsupported integer type u12"),
+                Error::new("unsupported integer type u300"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,558 @@
+use field::Field;
+use flat_ast::*;
+use num_bigint::BigUint;
+use num_traits::One;
+use std::collections::HashMap;
+use std::mem;
+use typed_ast::{Expression, Function, Type, Variable};
+
+// whether values of type `ty` hold optional values or enums, whose unused slots are constrained
+fn has_payloads(ty: &Type) -> bool {
+    match *ty {
+        Type::Option(..) | Type::Enum(..) => true,
+        Type::Array(ref inner, _) => has_payloads(inner),
+        Type::Struct(ref members) => members.iter().any(|(_, ty)| has_payloads(ty)),
+        Type::Tuple(ref elements) => elements.iter().any(has_payloads),
+        _ => false,
+    }
+}
+
+// What a variable name resolves to
+#[derive(Debug, Clone)]
+enum Binding<T: Field> {
+    // the SSA version it is laid out on: `a_{i}` at version 0, `a~{version}_{i}` after that
+    Version(usize),
+    // a value it stands for, such as the counter of an unrolled loop
+    Value(Vec<LinComb<T>>),
+}
+
+// Collects the statements of the function being flattened, and hands out fresh variables for
+// the intermediate values expressions need. Calls are inlined, looking the callee up in
+// `functions`
+#[derive(Debug)]
+pub struct Flattener<'ast, T: Field> {
+    statements: Vec<FlatStatement<T>>,
+    next_var_idx: usize,
+    functions: HashMap<String, &'ast Function<T>>,
+    // prepended to the flat variables of the function being inlined, so that they do not collide
+    // with the caller's
+    prefix: String,
+    next_call_idx: usize,
+    // the latest SSA version handed out for each name
+    versions: HashMap<String, usize>,
+    // what each name currently resolves to
+    bindings: HashMap<String, Binding<T>>,
+    // for each open block, the bindings its declarations shadow, to be restored when it ends
+    blocks: Vec<Vec<(String, Option<Binding<T>>)>>,
+    // for each open match arm, a bit which is set if and only if it and the arms around it are
+    // selected. The assertions of the innermost one only have to hold when its bit is set
+    gates: Vec<LinComb<T>>,
+    // what is wrong with the constants folded so far, such as indices out of bounds. The checker
+    // cannot see them until loops are unrolled and calls inlined
+    errors: Vec<String>,
+}
+
+// not derived, as that would require `T: Default`
+impl<'ast, T: Field> Default for Flattener<'ast, T> {
+    fn default() -> Self {
+        Flattener {
+            statements: vec![],
+            next_var_idx: 0,
+            functions: HashMap::new(),
+            prefix: String::new(),
+            next_call_idx: 0,
+            versions: HashMap::new(),
+            bindings: HashMap::new(),
+            blocks: vec![],
+            gates: vec![],
+            errors: vec![],
+        }
+    }
+}
+
+impl<'ast, T: Field> Flattener<'ast, T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_functions(functions: &'ast [Function<T>]) -> Self {
+        Flattener {
+            functions: functions.iter().map(|f| (f.id().clone(), f)).collect(),
+            ..Self::default()
+        }
+    }
+
+    // under a gate `g`, `a * b == c` becomes `a * b == p` for a fresh `p`, which always holds,
+    // and `g * (p - c) == 0`
+    pub fn push(&mut self, statement: FlatStatement<T>) {
+        let statement = match (self.gates.last().cloned(), statement) {
+            (Some(gate), FlatStatement::Assertion(a, b, c)) => {
+                let product = match (a.as_constant(), b.as_constant()) {
+                    (Some(a), _) => b.scale(a),
+                    (_, Some(b)) => a.scale(b),
+                    _ => {
+                        let product = self.use_sym();
+                        self.statements.push(FlatStatement::Directive(
+                            vec![product.clone()],
+                            Helper::Product,
+                            vec![a.clone(), b.clone()],
+                        ));
+                        self.statements.push(FlatStatement::Assertion(
+                            a,
+                            b,
+                            LinComb::from(product.clone()),
+                        ));
+                        LinComb::from(product)
+                    }
+                };
+                FlatStatement::Assertion(gate, product - c, LinComb::constant(T::zero()))
+            }
+            (_, statement) => statement,
+        };
+        self.statements.push(statement);
+    }
+
+    // reports a folded constant the program cannot be flattened with. Flattening goes on, so that
+    // every such constant is reported
+    pub fn error<S: Into<String>>(&mut self, message: S) {
+        self.errors.push(message.into());
+    }
+
+    pub fn errors(&self) -> &Vec<String> {
+        &self.errors
+    }
+
+    pub fn use_sym(&mut self) -> FlatVariable {
+        let var = FlatVariable::with_name(format!("~{}", self.next_var_idx));
+        self.next_var_idx += 1;
+        var
+    }
+
+    // the flat variables the version of `v` in scope is laid out on
+    pub fn flat_variables(&self, v: &Variable) -> Vec<FlatVariable> {
+        let name = self.name(v);
+        let name = match self.bindings.get(&name) {
+            Some(Binding::Version(version)) if *version > 0 => format!("{}~{}", name, version),
+            _ => name,
+        };
+        Variable::new(name, v.get_type()).flat_variables()
+    }
+
+    // what `v` evaluates to: its flat variables, unless it stands for a value
+    pub fn identifier(&self, v: &Variable) -> Vec<LinComb<T>> {
+        match self.bindings.get(&self.name(v)) {
+            Some(Binding::Value(e)) => e.clone(),
+            _ => self
+                .flat_variables(v)
+                .into_iter()
+                .map(LinComb::from)
+                .collect(),
+        }
+    }
+
+    // the assertions pushed until the matching `exit_gate` only have to hold when the bit
+    // `selected` is set, as well as the gates already open
+    pub fn enter_gate(&mut self, selected: LinComb<T>) {
+        let outer = mem::replace(&mut self.gates, vec![]);
+        let gate = match outer.last() {
+            Some(gate) => self.mult(gate.clone(), selected),
+            None => selected,
+        };
+        self.gates = outer;
+        self.gates.push(gate);
+    }
+
+    pub fn exit_gate(&mut self) {
+        self.gates.pop();
+    }
+
+    pub fn enter_block(&mut self) {
+        self.blocks.push(vec![]);
+    }
+
+    // the declarations of the block go out of scope
+    pub fn exit_block(&mut self) {
+        let shadowed = self.blocks.pop().unwrap();
+        for (name, binding) in shadowed.into_iter().rev() {
+            match binding {
+                Some(binding) => self.bindings.insert(name, binding),
+                None => self.bindings.remove(&name),
+            };
+        }
+    }
+
+    // declares `v` in the current block, laid out on a new version
+    pub fn declare(&mut self, v: &Variable) -> Vec<FlatVariable> {
+        let version = self.next_version(v);
+        self.bind_name(v, Binding::Version(version));
+        self.flat_variables(v)
+    }
+
+    // declares `v` in the current block, standing for `e`
+    pub fn substitute(&mut self, v: &Variable, e: Vec<LinComb<T>>) {
+        self.bind_name(v, Binding::Value(e));
+    }
+
+    // a new version of the `v` in scope, which can be declared in an enclosing block
+    pub fn reassign(&mut self, v: &Variable) -> Vec<FlatVariable> {
+        let version = self.next_version(v);
+        let name = self.name(v);
+        self.bindings.insert(name, Binding::Version(version));
+        self.flat_variables(v)
+    }
+
+    fn next_version(&mut self, v: &Variable) -> usize {
+        let name = self.name(v);
+        *self
+            .versions
+            .entry(name)
+            .and_modify(|version| *version += 1)
+            .or_insert(0)
+    }
+
+    fn bind_name(&mut self, v: &Variable, binding: Binding<T>) {
+        let name = self.name(v);
+        let shadowed = self.bindings.insert(name.clone(), binding);
+        if let Some(block) = self.blocks.last_mut() {
+            block.push((name, shadowed));
+        }
+    }
+
+    // the name of `v` in the function being flattened
+    fn name(&self, v: &Variable) -> String {
+        format!("{}{}", self.prefix, v.name())
+    }
+
+    // binds each of `vars` to the matching slot of `e`
+    pub fn bind(&mut self, vars: &[FlatVariable], e: Vec<LinComb<T>>) {
+        assert_eq!(vars.len(), e.len(), "cannot bind {} slots to {} values", vars.len(), e.len());
+        self.push(FlatStatement::Directive(
+            vars.to_vec(),
+            Helper::Identity,
+            e.clone(),
+        ));
+        for (var, e) in vars.iter().zip(e.into_iter()) {
+            self.push(FlatStatement::Assertion(
+                LinComb::from(var.clone()),
+                LinComb::constant(T::one()),
+                e,
+            ));
+        }
+    }
+
+    // declares `v` and binds its slots to `e`, checking that boolean slots are bits
+    pub fn define(&mut self, v: &Variable, e: Vec<LinComb<T>>) {
+        let vars = self.declare(v);
+
+        self.bind(&vars, e);
+        self.assert_bits(&Self::slots(&vars), &v.get_type());
+    }
+
+    // binds the slots of a new version of the `v` in scope to `e`
+    pub fn assign(&mut self, v: &Variable, e: Vec<LinComb<T>>) {
+        let vars = self.reassign(v);
+
+        self.bind(&vars, e);
+        self.assert_bits(&Self::slots(&vars), &v.get_type());
+    }
+
+    // the values laid out on `vars`
+    pub fn slots(vars: &[FlatVariable]) -> Vec<LinComb<T>> {
+        vars.iter().cloned().map(LinComb::from).collect()
+    }
+
+    // checks that the boolean slots of a value of type `ty` laid out on `slots` are bits, that its
+    // integer slots fit in their width, and that its absent payloads are all zeros
+    pub fn assert_bits(&mut self, slots: &[LinComb<T>], ty: &Type) {
+        for (slot, ty) in slots.iter().zip(ty.flat_types()) {
+            match ty {
+                Type::Boolean => self.assert_bit(slot.clone()),
+                Type::Uint(bitwidth) => {
+                    self.bits(slot.clone(), bitwidth);
+                }
+                _ => {}
+            }
+        }
+        self.assert_empty_payloads(slots, ty);
+    }
+
+    // `(1 - flag) * slot == 0` for each slot of each optional value, and the same for the slots
+    // of an enum past the end of the payload of its variant, so that such values have a single
+    // witness. The types of the payloads of enums are checked when matched, but the optional
+    // values they hold are constrained here
+    fn assert_empty_payloads(&mut self, slots: &[LinComb<T>], ty: &Type) {
+        match *ty {
+            Type::Option(ref inner) => {
+                let absent = LinComb::constant(T::one()) - slots[0].clone();
+                for slot in &slots[1..] {
+                    self.push(FlatStatement::Assertion(
+                        absent.clone(),
+                        slot.clone(),
+                        LinComb::constant(T::zero()),
+                    ));
+                }
+                self.assert_empty_payloads(&slots[1..], inner);
+            }
+            Type::Enum(_, ref variants) => {
+                let selectors: Vec<_> = (0..variants.len())
+                    .map(|t| self.eq(slots[0].clone(), LinComb::constant(T::from(t))))
+                    .collect();
+
+                // exactly one variant has the tag
+                let sum = selectors
+                    .iter()
+                    .fold(LinComb(vec![]), |acc, selected| acc + selected.clone());
+                self.push(FlatStatement::Assertion(
+                    sum,
+                    LinComb::constant(T::one()),
+                    LinComb::constant(T::one()),
+                ));
+
+                // a slot is zero unless the payload of the selected variant reaches it
+                for (i, slot) in slots[1..].iter().enumerate() {
+                    let using: Vec<_> = variants
+                        .iter()
+                        .zip(&selectors)
+                        .filter(|((_, ty), _)| ty.size() > i)
+                        .map(|(_, selected)| selected.clone())
+                        .collect();
+                    if using.len() == variants.len() {
+                        continue;
+                    }
+                    let used = using.into_iter().fold(LinComb(vec![]), |acc, s| acc + s);
+                    self.push(FlatStatement::Assertion(
+                        LinComb::constant(T::one()) - used,
+                        slot.clone(),
+                        LinComb::constant(T::zero()),
+                    ));
+                }
+
+                // the payload of each variant, masked by whether it is selected so that the others
+                // are all zeros
+                for ((_, ty), selected) in variants.iter().zip(selectors) {
+                    if !has_payloads(ty) {
+                        continue;
+                    }
+                    let masked: Vec<_> = slots[1..1 + ty.size()]
+                        .iter()
+                        .map(|slot| self.mult(selected.clone(), slot.clone()))
+                        .collect();
+                    self.assert_empty_payloads(&masked, ty);
+                }
+            }
+            Type::Array(ref inner, size) => {
+                let types = vec![(**inner).clone(); size];
+                self.assert_consecutive_payloads(slots, &types);
+            }
+            Type::Struct(ref members) => {
+                let types: Vec<_> = members.iter().map(|(_, ty)| ty.clone()).collect();
+                self.assert_consecutive_payloads(slots, &types);
+            }
+            Type::Tuple(ref elements) => self.assert_consecutive_payloads(slots, elements),
+            _ => {}
+        }
+    }
+
+    // the values of `types` being laid out one after the other on `slots`
+    fn assert_consecutive_payloads(&mut self, slots: &[LinComb<T>], types: &[Type]) {
+        let mut offset = 0;
+        for ty in types {
+            self.assert_empty_payloads(&slots[offset..offset + ty.size()], ty);
+            offset += ty.size();
+        }
+    }
+
+    // inlines the body of `id` with its parameters bound to `arguments`, and returns what it
+    // returns
+    pub fn call(&mut self, id: &str, arguments: &[Box<Expression<T>>]) -> Vec<LinComb<T>> {
+        let function = match self.functions.get(id) {
+            Some(f) => *f,
+            None => panic!("undefined function {}", id),
+        };
+
+        let arity = function.arguments().len();
+        assert_eq!(
+            arity,
+            arguments.len(),
+            "cannot call {} taking {} parameters with {} arguments",
+            id,
+            arity,
+            arguments.len()
+        );
+
+        // the arguments are flattened in the caller
+        let arguments: Vec<_> = arguments.iter().map(|a| a.flatten(self)).collect();
+
+        let prefix = format!("{}{}~{}.", self.prefix, id, self.next_call_idx);
+        self.next_call_idx += 1;
+        let caller_prefix = mem::replace(&mut self.prefix, prefix);
+
+        for (parameter, argument) in function.arguments().iter().zip(arguments) {
+            self.define(parameter.variable(), argument);
+        }
+        let outputs = match function.flatten_body(self) {
+            Some(outputs) => outputs,
+            None => panic!("{} does not return a value", id),
+        };
+
+        self.prefix = caller_prefix;
+        outputs
+    }
+
+    // `a * b`, which takes a fresh variable and a constraint unless one side is constant
+    pub fn mult(&mut self, a: LinComb<T>, b: LinComb<T>) -> LinComb<T> {
+        if let Some(a) = a.as_constant() {
+            return b.scale(a);
+        }
+        if let Some(b) = b.as_constant() {
+            return a.scale(b);
+        }
+
+        let product = self.use_sym();
+        self.push(FlatStatement::Directive(
+            vec![product.clone()],
+            Helper::Product,
+            vec![a.clone(), b.clone()],
+        ));
+        self.push(FlatStatement::Assertion(a, b, LinComb::from(product.clone())));
+        LinComb::from(product)
+    }
+
+    // `1 / a`, a fresh variable constrained by `a * (1 / a) == 1`
+    pub fn inverse(&mut self, a: LinComb<T>) -> LinComb<T> {
+        let inverse = self.inverse_or_zero(a.clone());
+        self.push(FlatStatement::Assertion(
+            a,
+            inverse.clone(),
+            LinComb::constant(T::one()),
+        ));
+        inverse
+    }
+
+    // `x * (1 - x) == 0`
+    pub fn assert_bit(&mut self, x: LinComb<T>) {
+        self.push(FlatStatement::Assertion(
+            x.clone(),
+            LinComb::constant(T::one()) - x,
+            LinComb::constant(T::zero()),
+        ));
+    }
+
+    // the `count` bits of `a`, least significant first, constrained to pack back into `a`
+    pub fn bits(&mut self, a: LinComb<T>, count: usize) -> Vec<LinComb<T>> {
+        if let Some(a) = a.as_constant() {
+            let a = a.to_biguint();
+            assert!(a.bits() <= count, "{} does not fit in {} bits", a, count);
+            return (0..count)
+                .map(|i| {
+                    if ((&a >> i) % BigUint::from(2u32)).is_one() {
+                        LinComb::constant(T::one())
+                    } else {
+                        LinComb::constant(T::zero())
+                    }
+                }).collect();
+        }
+
+        let bits: Vec<_> = (0..count).map(|_| self.use_sym()).collect();
+        self.push(FlatStatement::Directive(
+            bits.clone(),
+            Helper::Bits,
+            vec![a.clone()],
+        ));
+        for b in &bits {
+            self.assert_bit(LinComb::from(b.clone()));
+        }
+        self.push(FlatStatement::Assertion(
+            LinComb(
+                bits.iter()
+                    .enumerate()
+                    .map(|(i, b)| (T::from(2).pow(i), b.clone()))
+                    .collect(),
+            ),
+            LinComb::constant(T::one()),
+            a,
+        ));
+
+        bits.into_iter().map(LinComb::from).collect()
+    }
+
+    // the value the `bits` stand for, least significant first
+    pub fn pack(bits: &[LinComb<T>]) -> LinComb<T> {
+        bits.iter()
+            .enumerate()
+            .fold(LinComb(vec![]), |acc, (i, b)| acc + b.clone().scale(T::from(2).pow(i)))
+    }
+
+    // `a == b` as a bit: `a - b` has an inverse `i` if and only if they differ, so with
+    // `e := 1 - (a - b) * i` we get `(a - b) * e == 0`
+    pub fn eq(&mut self, a: LinComb<T>, b: LinComb<T>) -> LinComb<T> {
+        if let (Some(a), Some(b)) = (a.as_constant(), b.as_constant()) {
+            return LinComb::constant(if a == b { T::one() } else { T::zero() });
+        }
+
+        let difference = a - b;
+        let inverse = self.inverse_or_zero(difference.clone());
+        let product = self.mult(difference.clone(), inverse);
+        let eq = LinComb::constant(T::one()) - product;
+        self.push(FlatStatement::Assertion(
+            difference,
+            eq.clone(),
+            LinComb::constant(T::zero()),
+        ));
+        eq
+    }
+
+    // `a < b` as a bit. Both sides are range checked to `n - 2` bits, `n` being the bit width of
+    // the field, so that `2^(n - 2) + a - b` is positive and fits in `n - 1` bits, the top one
+    // being set if and only if `a >= b`. Constant sides are held to the same bound, and reported
+    // and replaced with zero when they break it
+    pub fn lt(&mut self, a: LinComb<T>, b: LinComb<T>) -> LinComb<T> {
+        let width = T::get_required_bits() - 2;
+
+        let mut sides = vec![];
+        for e in vec![a, b] {
+            let e = match e.as_constant() {
+                Some(ref v) if v.to_biguint().bits() > width => {
+                    self.error(format!(
+                        "cannot compare {}, which does not fit in {} bits",
+                        v, width
+                    ));
+                    LinComb::constant(T::zero())
+                }
+                _ => e,
+            };
+            self.bits(e.clone(), width);
+            sides.push(e);
+        }
+        let b = sides.pop().unwrap();
+        let a = sides.pop().unwrap();
+
+        if let (Some(a), Some(b)) = (a.as_constant(), b.as_constant()) {
+            let lt = a.to_biguint() < b.to_biguint();
+            return LinComb::constant(if lt { T::one() } else { T::zero() });
+        }
+
+        let shifted = LinComb::constant(T::from(2).pow(width)) + a - b;
+        let top = self.bits(shifted, width + 1).pop().unwrap();
+        LinComb::constant(T::one()) - top
+    }
+
+    // a fresh variable that is the inverse of `a` when it has one, without constraining it
+    fn inverse_or_zero(&mut self, a: LinComb<T>) -> LinComb<T> {
+        let inverse = self.use_sym();
+        self.push(FlatStatement::Directive(
+            vec![inverse.clone()],
+            Helper::Inverse,
+            vec![a],
+        ));
+        LinComb::from(inverse)
+    }
+
+    pub fn statements(&self) -> &Vec<FlatStatement<T>> {
+        &self.statements
+    }
+
+    pub fn into_statements(self) -> Vec<FlatStatement<T>> {
+        self.statements
+    }
+}
@@ -1,142 +1,693 @@
+mod flattener;
 mod types;
 
-use self::types::*;
+pub use self::flattener::Flattener;
+pub use self::types::*;
+use field::Field;
 use flat_ast::*;
+use std::collections::HashMap;
+use std::fmt;
 use std::fmt::{Debug, Display};
 
 ///////////////////////////////////////////////////////////////////
 
 // A trait for all expressions to implement
-pub trait Expression: Debug + Display {
-    fn flatten(&self, flattened_statements: &mut Vec<FlatStatement>) -> Vec<LinComb>;
+pub trait Expression<T: Field>: Debug + Display {
+    fn flatten(&self, flattener: &mut Flattener<T>) -> Vec<LinComb<T>>;
+    fn get_type(&self) -> Type;
+}
+
+// arrays of arrays are built out of boxed elements when their nesting is only known at runtime
+impl<T: Field> Expression<T> for Box<Expression<T>> {
+    fn flatten(&self, flattener: &mut Flattener<T>) -> Vec<LinComb<T>> {
+        (**self).flatten(flattener)
+    }
+
+    fn get_type(&self) -> Type {
+        (**self).get_type()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TypeError(String);
+
+impl TypeError {
+    pub fn new<S: Into<String>>(message: S) -> Self {
+        TypeError(message.into())
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Type {
+    FieldElement,
+    Boolean,
+    // an unsigned integer of the given bit width, which is 8, 16, 32 or 64
+    Uint(usize),
+    Array(Box<Type>, usize),
+    // an array whose size is a parameter of a generic function, as in `field[N]`. It only appears
+    // in generic functions, and is resolved when they are instantiated
+    GenericArray(Box<Type>, String),
+    Struct(Vec<(String, Type)>),
+    Tuple(Vec<Type>),
+    // a named sum type, each variant holding a value of its own type
+    Enum(String, Vec<(String, Type)>),
+    // a presence flag, then the value, which is zero when absent
+    Option(Box<Type>),
+}
+
+impl Type {
+    // the number of field elements a value of this type flattens to
+    pub fn size(&self) -> usize {
+        match *self {
+            Type::FieldElement | Type::Boolean | Type::Uint(..) => 1,
+            Type::Array(ref ty, size) => ty.size() * size,
+            Type::Struct(ref members) => members.iter().map(|(_, ty)| ty.size()).sum(),
+            Type::Tuple(ref elements) => elements.iter().map(|ty| ty.size()).sum(),
+            // the tag, then the largest payload
+            Type::Enum(_, ref variants) => {
+                1 + variants.iter().map(|(_, ty)| ty.size()).max().unwrap_or(0)
+            }
+            Type::Option(ref ty) => 1 + ty.size(),
+            Type::GenericArray(..) => {
+                panic!("the size of {} is not known until instantiated", self)
+            }
+        }
+    }
+
+    // the primitive type of each of the slots this type flattens to
+    pub fn flat_types(&self) -> Vec<Type> {
+        match *self {
+            Type::FieldElement | Type::Boolean | Type::Uint(..) => vec![self.clone()],
+            Type::Array(ref ty, size) => (0..size).flat_map(|_| ty.flat_types()).collect(),
+            Type::Struct(ref members) => members
+                .iter()
+                .flat_map(|(_, ty)| ty.flat_types())
+                .collect(),
+            Type::Tuple(ref elements) => elements.iter().flat_map(|ty| ty.flat_types()).collect(),
+            // a payload slot keeps its type if all the variants using it agree. The slots are
+            // checked against the payload of the variant when matched, the unused ones being zero
+            Type::Enum(_, ref variants) => {
+                let payloads: Vec<_> = variants.iter().map(|(_, ty)| ty.flat_types()).collect();
+                let slots = (0..self.size() - 1).map(|i| {
+                    let mut types = payloads.iter().filter_map(|p| p.get(i));
+                    let first = types.next().unwrap();
+                    match types.all(|ty| ty == first) {
+                        true => first.clone(),
+                        false => Type::FieldElement,
+                    }
+                });
+                Some(Type::FieldElement).into_iter().chain(slots).collect()
+            }
+            Type::Option(ref ty) => Some(Type::Boolean)
+                .into_iter()
+                .chain(ty.flat_types())
+                .collect(),
+            Type::GenericArray(..) => {
+                panic!("the slots of {} are not known until instantiated", self)
+            }
+        }
+    }
+
+    // the offset of the member `id` in the flattened struct, and its type
+    pub fn member(&self, id: &str) -> Option<(usize, &Type)> {
+        match *self {
+            Type::Struct(ref members) => {
+                let mut offset = 0;
+                for (name, ty) in members {
+                    if name == id {
+                        return Some((offset, ty));
+                    }
+                    offset += ty.size();
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    // the tag of the variant `id`, and the type of its payload
+    pub fn variant(&self, id: &str) -> Option<(usize, &Type)> {
+        match *self {
+            Type::Enum(_, ref variants) => variants
+                .iter()
+                .position(|(name, _)| name == id)
+                .map(|tag| (tag, &variants[tag].1)),
+            _ => None,
+        }
+    }
+
+    // this type with the sizes of its generic arrays taken from `sizes`
+    pub fn instantiate(&self, sizes: &HashMap<String, usize>) -> Result<Type, TypeError> {
+        Ok(match *self {
+            Type::FieldElement | Type::Boolean | Type::Uint(..) => self.clone(),
+            Type::Array(ref ty, size) => Type::Array(box ty.instantiate(sizes)?, size),
+            Type::GenericArray(ref ty, ref id) => match sizes.get(id) {
+                Some(size) => Type::Array(box ty.instantiate(sizes)?, *size),
+                None => return Err(TypeError::new(format!("undeclared size {}", id))),
+            },
+            Type::Struct(ref members) => Type::Struct(
+                members
+                    .iter()
+                    .map(|(id, ty)| ty.instantiate(sizes).map(|ty| (id.clone(), ty)))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Type::Tuple(ref elements) => Type::Tuple(
+                elements
+                    .iter()
+                    .map(|ty| ty.instantiate(sizes))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Type::Enum(ref id, ref variants) => Type::Enum(
+                id.clone(),
+                variants
+                    .iter()
+                    .map(|(id, ty)| ty.instantiate(sizes).map(|ty| (id.clone(), ty)))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Type::Option(ref ty) => Type::Option(box ty.instantiate(sizes)?),
+        })
+    }
+
+    // the offset of the element at `index` in the flattened tuple, and its type
+    pub fn element(&self, index: usize) -> Option<(usize, &Type)> {
+        match *self {
+            Type::Tuple(ref elements) => elements.get(index).map(|ty| {
+                let offset = elements[..index].iter().map(|ty| ty.size()).sum();
+                (offset, ty)
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Type::FieldElement => write!(f, "field"),
+            Type::Boolean => write!(f, "bool"),
+            Type::Uint(bitwidth) => write!(f, "u{}", bitwidth),
+            Type::Array(ref ty, size) => write!(f, "{}[{}]", ty, size),
+            Type::GenericArray(ref ty, ref id) => write!(f, "{}[{}]", ty, id),
+            Type::Struct(ref members) => write!(
+                f,
+                "{{{}}}",
+                members
+                    .iter()
+                    .map(|(id, ty)| format!("{}: {}", id, ty))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Type::Enum(ref id, _) => write!(f, "{}", id),
+            Type::Option(ref ty) => write!(f, "Option<{}>", ty),
+            // a single element is followed by a comma, as in `(field,)`
+            Type::Tuple(ref elements) => match elements.len() {
+                1 => write!(f, "({},)", elements[0]),
+                _ => write!(
+                    f,
+                    "({})",
+                    elements
+                        .iter()
+                        .map(|ty| format!("{}", ty))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            },
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
-pub struct Variable(String);
+pub struct Variable {
+    name: String,
+    _type: Type,
+}
 
 impl Variable {
-    pub fn with_name<S: Into<String>>(name: S) -> Self {
-        Variable(name.into())
+    pub fn new<S: Into<String>>(name: S, _type: Type) -> Self {
+        Variable {
+            name: name.into(),
+            _type,
+        }
+    }
+
+    pub fn field_element<S: Into<String>>(name: S) -> Self {
+        Self::new(name, Type::FieldElement)
+    }
+
+    pub fn boolean<S: Into<String>>(name: S) -> Self {
+        Self::new(name, Type::Boolean)
+    }
+
+    pub fn array<S: Into<String>>(name: S, inner: Type, size: usize) -> Self {
+        Self::new(name, Type::Array(box inner, size))
+    }
+
+    pub fn structure<S: Into<String>>(name: S, members: Vec<(String, Type)>) -> Self {
+        Self::new(name, Type::Struct(members))
+    }
+
+    pub fn tuple<S: Into<String>>(name: S, elements: Vec<Type>) -> Self {
+        Self::new(name, Type::Tuple(elements))
     }
 
     pub fn name(&self) -> &String {
-        &self.0
+        &self.name
+    }
+
+    pub fn get_type(&self) -> Type {
+        self._type.clone()
+    }
+
+    // the flat variables this variable is laid out on: `{name}_0`, `{name}_1`, ...
+    pub fn flat_variables(&self) -> Vec<FlatVariable> {
+        (0..self._type.size())
+            .map(|index| FlatVariable::with_name(format!("{}_{}", self.name, index)))
+            .collect()
+    }
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+// the types a function takes and returns
+#[derive(Debug, PartialEq, Clone)]
+pub struct Signature {
+    pub inputs: Vec<Type>,
+    pub outputs: Vec<Type>,
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "({}) -> ({})",
+            self.inputs
+                .iter()
+                .map(|ty| ty.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            self.outputs
+                .iter()
+                .map(|ty| ty.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Parameter {
+    variable: Variable,
+    private: bool,
+}
+
+impl Parameter {
+    pub fn new(variable: Variable, private: bool) -> Self {
+        Parameter { variable, private }
+    }
+
+    pub fn public(variable: Variable) -> Self {
+        Self::new(variable, false)
+    }
+
+    pub fn private(variable: Variable) -> Self {
+        Self::new(variable, true)
+    }
+
+    pub fn variable(&self) -> &Variable {
+        &self.variable
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.private
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.private {
+            true => write!(f, "private {} {}", self.variable.get_type(), self.variable),
+            false => write!(f, "{} {}", self.variable.get_type(), self.variable),
+        }
     }
 }
 
 #[derive(Debug)]
-enum Statement {
-    Definition(Variable, Box<Expression>),
-    Return(Vec<Box<Expression>>),
+pub enum Statement<T: Field> {
+    Definition(Variable, Box<Expression<T>>),
+    // defines each variable as the matching element of a tuple
+    MultipleDefinition(Vec<Variable>, Box<Expression<T>>),
+    // the new value must have the type `v` was defined with
+    Assignment(Variable, Box<Expression<T>>),
+    Return(Vec<Box<Expression<T>>>),
+    // `for i in start..end`, unrolled when flattening
+    For(Variable, usize, usize, Vec<Statement<T>>),
+    // two values of the same type, constrained to be equal slot by slot
+    AssertEq(Box<Expression<T>>, Box<Expression<T>>),
 }
 
-impl Statement {
-    fn flatten(self, flattened_statements: &mut Vec<FlatStatement>) {
-        match self {
-            Statement::Definition(v, e) => {
-                let e = e.flatten(flattened_statements);
-
-                flattened_statements.push(FlatStatement::Directive(
-                    e.iter()
-                        .enumerate()
-                        .map(|(index, _)| {
-                            FlatVariable::with_name(format!("{}_{}", v.name(), index))
-                        }).collect(),
-                ));
-                for (index, e) in e.iter().enumerate() {
-                    flattened_statements.push(FlatStatement::Assertion(
-                        LinComb(vec![(
-                            1,
-                            FlatVariable::with_name(format!("{}_{}", v.name(), index)),
-                        )]),
-                        LinComb(vec![(1, FlatVariable::one())]),
-                        e.clone(),
-                    ));
+impl<T: Field> Statement<T> {
+    // returns the flattened values of a return statement
+    fn flatten(&self, flattener: &mut Flattener<T>) -> Option<Vec<LinComb<T>>> {
+        match *self {
+            Statement::Definition(ref v, ref e) => {
+                let e = e.flatten(flattener);
+                flattener.define(v, e);
+                None
+            }
+            Statement::MultipleDefinition(ref vars, ref e) => {
+                let e = e.flatten(flattener);
+                let size: usize = vars.iter().map(|v| v.get_type().size()).sum();
+                assert_eq!(size, e.len(), "cannot define {} slots from {} values", size, e.len());
+                let mut e = e.into_iter();
+                for v in vars {
+                    let slots = e.by_ref().take(v.get_type().size()).collect();
+                    flattener.define(v, slots);
                 }
+                None
+            }
+            Statement::Assignment(ref v, ref e) => {
+                let e = e.flatten(flattener);
+                flattener.assign(v, e);
+                None
+            }
+            Statement::Return(ref e) => {
+                Some(e.iter().flat_map(|e| e.flatten(flattener)).collect())
+            }
+            Statement::For(ref v, start, end, ref statements) => {
+                let mut outputs = None;
+                for i in start..end {
+                    flattener.enter_block();
+                    flattener.substitute(v, vec![LinComb::constant(T::from(i))]);
+                    for s in statements {
+                        if let Some(e) = s.flatten(flattener) {
+                            outputs = Some(e);
+                        }
+                    }
+                    flattener.exit_block();
+                }
+                outputs
+            }
+            Statement::AssertEq(ref e1, ref e2) => {
+                let e1 = e1.flatten(flattener);
+                let e2 = e2.flatten(flattener);
+                assert_eq!(
+                    e1.len(),
+                    e2.len(),
+                    "cannot compare {} slots to {} slots",
+                    e1.len(),
+                    e2.len()
+                );
+                for (a, b) in e1.into_iter().zip(e2) {
+                    flattener.push(FlatStatement::Assertion(a, LinComb::constant(T::one()), b));
+                }
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Function<T: Field> {
+    id: String,
+    arguments: Vec<Parameter>,
+    returns: Vec<Type>,
+    statements: Vec<Statement<T>>,
+}
+
+impl<T: Field> Function<T> {
+    pub fn new<S: Into<String>>(
+        id: S,
+        arguments: Vec<Parameter>,
+        returns: Vec<Type>,
+        statements: Vec<Statement<T>>,
+    ) -> Self {
+        Function {
+            id: id.into(),
+            arguments,
+            returns,
+            statements,
+        }
+    }
+
+    pub fn with_statements(statements: Vec<Statement<T>>) -> Self {
+        Self::new("main", vec![], vec![], statements)
+    }
+
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn arguments(&self) -> &Vec<Parameter> {
+        &self.arguments
+    }
+
+    pub fn signature(&self) -> Signature {
+        Signature {
+            inputs: self
+                .arguments
+                .iter()
+                .map(|p| p.variable().get_type())
+                .collect(),
+            outputs: self.returns.clone(),
+        }
+    }
+
+    pub fn flatten(&self) -> FlatFunction<T> {
+        self.flatten_with(&[])
+    }
+
+    // flattens this function, inlining the calls it makes to `functions`. The parameters are
+    // the inputs, and each slot of the returned values is bound to an output variable
+    pub fn flatten_with(&self, functions: &[Function<T>]) -> FlatFunction<T> {
+        match self.try_flatten_with(functions) {
+            Ok(f) => f,
+            Err(e) => panic!("cannot flatten {}: {}", self.id, e.join(", ")),
+        }
+    }
+
+    // like `flatten_with`, but returns what is wrong with the constants folded along the way,
+    // which the checker reports
+    pub fn try_flatten_with(
+        &self,
+        functions: &[Function<T>],
+    ) -> Result<FlatFunction<T>, Vec<String>> {
+        let mut flattener = Flattener::with_functions(functions);
+        let mut public_inputs = vec![];
+        let mut private_inputs = vec![];
+        let mut outputs = vec![];
+
+        for p in &self.arguments {
+            let v = p.variable();
+            let vars = flattener.declare(v);
+            flattener.assert_bits(&Flattener::slots(&vars), &v.get_type());
+            match p.is_private() {
+                true => private_inputs.extend(vars),
+                false => public_inputs.extend(vars),
             }
-            Statement::Return(e) => {
-                flattened_statements.push(FlatStatement::Return(vec![]));
+        }
+
+        if let Some(e) = self.flatten_body(&mut flattener) {
+            outputs = (0..e.len()).map(FlatVariable::output).collect();
+            flattener.bind(&outputs, e);
+            flattener.push(FlatStatement::Return(outputs.clone()));
+        }
+
+        if !flattener.errors().is_empty() {
+            return Err(flattener.errors().clone());
+        }
+
+        Ok(FlatFunction::new(
+            public_inputs,
+            private_inputs,
+            outputs,
+            flattener.into_statements(),
+        ))
+    }
+
+    // flattens the statements, and returns the flattened values of the return statement if
+    // there is one
+    fn flatten_body(&self, flattener: &mut Flattener<T>) -> Option<Vec<LinComb<T>>> {
+        let mut outputs = None;
+        for s in &self.statements {
+            if let Some(e) = s.flatten(flattener) {
+                outputs = Some(e);
             }
         }
+        outputs
     }
 }
 
+// Several functions, among which `main` is the entry point. Every function can only call the
+// ones defined before it
 #[derive(Debug)]
-struct Function {
-    statements: Vec<Statement>,
+pub struct Program<T: Field> {
+    functions: Vec<Function<T>>,
 }
 
-impl Function {
-    fn flatten(self) -> FlatFunction {
-        let mut flattened_statements = vec![];
+impl<T: Field> Program<T> {
+    pub fn with_functions(functions: Vec<Function<T>>) -> Self {
+        Program { functions }
+    }
+
+    pub fn functions(&self) -> &Vec<Function<T>> {
+        &self.functions
+    }
+
+    // flattens each function on its own, with the calls it makes inlined
+    pub fn flatten(&self) -> FlatProg<T> {
+        match self.try_flatten() {
+            Ok(p) => p,
+            Err(e) => panic!("cannot flatten program: {}", e.join(", ")),
+        }
+    }
 
-        for s in self.statements {
-            s.flatten(&mut flattened_statements)
+    // like `flatten`, but returns the errors of all functions. An error in a function shows up
+    // again in each function it is inlined in, so it is only returned once
+    pub fn try_flatten(&self) -> Result<FlatProg<T>, Vec<String>> {
+        let mut functions = vec![];
+        let mut errors: Vec<String> = vec![];
+
+        for f in &self.functions {
+            match f.try_flatten_with(&self.functions) {
+                Ok(flattened) => functions.push((f.id().clone(), flattened)),
+                Err(e) => {
+                    for e in e {
+                        if !errors.contains(&e) {
+                            errors.push(e);
+                        }
+                    }
+                }
+            }
         }
 
-        FlatFunction::with_statements(flattened_statements)
+        if errors.is_empty() {
+            Ok(FlatProg::with_functions(functions))
+        } else {
+            Err(errors)
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use field::{Bn128Field, Field};
 
     #[test]
     fn array_expression() {
-        let v0 = Array::value(vec![FieldElement::Value(0), FieldElement::Value(1)]);
+        let v0 = Array::value(vec![
+            FieldElement::Value(Bn128Field::from(0)),
+            FieldElement::Value(Bn128Field::from(1)),
+        ]).unwrap();
         println!("{}", v0);
     }
 
     #[test]
     fn nested_array_expression() {
         let v1 = Array::value(vec![
-            Array::value(vec![FieldElement::Value(0), FieldElement::Value(1)]),
-            Array::value(vec![FieldElement::Value(0), FieldElement::Value(1)]),
-        ]);
+            Array::value(vec![
+                FieldElement::Value(Bn128Field::from(0)),
+                FieldElement::Value(Bn128Field::from(1)),
+            ]).unwrap(),
+            Array::value(vec![
+                FieldElement::Value(Bn128Field::from(0)),
+                FieldElement::Value(Bn128Field::from(1)),
+            ]).unwrap(),
+        ]).unwrap();
         println!("{}", v1);
+        assert_eq!(
+            v1.get_type(),
+            Type::Array(box Type::Array(box Type::FieldElement, 2), 2)
+        );
+    }
+
+    #[test]
+    fn long_array_expression() {
+        // field[4][3]
+        let v = Array::value(
+            (0..4)
+                .map(|i| {
+                    Array::value(
+                        (0..3)
+                            .map(|j| FieldElement::Value(Bn128Field::from(3 * i + j)))
+                            .collect(),
+                    ).unwrap()
+                }).collect(),
+        ).unwrap();
+
+        assert_eq!(
+            v.get_type(),
+            Type::Array(box Type::Array(box Type::FieldElement, 3), 4)
+        );
+        assert_eq!(v.get_type().size(), 12);
+    }
+
+    #[test]
+    fn empty_array_expression() {
+        assert!(Array::<Bn128Field, FieldElement<Bn128Field>>::value(vec![]).is_err());
     }
 
     #[test]
     fn definition() {
-        let _s = Statement::Definition(Variable::with_name("a"), box Boolean::_true());
+        let _s = Statement::<Bn128Field>::Definition(Variable::boolean("a"), box Boolean::_true());
     }
 
     #[test]
     fn return_statement() {
-        let _r: Statement =
-            Statement::Return(vec![box Boolean::Value(true), box FieldElement::Value(1)]);
+        let _r: Statement<Bn128Field> = Statement::Return(vec![
+            box Boolean::Value(true),
+            box FieldElement::Value(Bn128Field::from(1)),
+        ]);
     }
 
     #[test]
-    #[should_panic]
     fn wrong_count_in_def() {
-        let _e2 = Array::value(vec![
+        let e2 = Array::value(vec![
             Array::value(vec![
-                FieldElement::Value(0), // /!\ here 1 element /!\
-            ]),
+                FieldElement::Value(Bn128Field::from(0)), // /!\ here 1 element /!\
+            ]).unwrap(),
             Array::value(vec![
-                FieldElement::Value(0), // here 3 elements
-                FieldElement::Value(1),
-            ]),
-        ]); // should panic
+                FieldElement::Value(Bn128Field::from(0)), // here 2 elements
+                FieldElement::Value(Bn128Field::from(1)),
+            ]).unwrap(),
+        ]);
+        assert_eq!(
+            e2.unwrap_err(),
+            TypeError::new("expected an element of type field[1], found [0, 1] of type field[2]")
+        );
     }
 
     #[test]
     fn flatten_function() {
         let f = Function {
+            id: "main".to_string(),
+            arguments: vec![],
+            returns: vec![],
             statements: vec![
-                Statement::Definition(Variable::with_name("a"), box FieldElement::Value(42)),
                 Statement::Definition(
-                    Variable::with_name("b"),
+                    Variable::field_element("a"),
+                    box FieldElement::Value(Bn128Field::from(42)),
+                ),
+                Statement::Definition(
+                    Variable::field_element("b"),
                     box FieldElement::Add(
-                        box FieldElement::Identifier(Variable::with_name("a")),
-                        box FieldElement::Value(3),
+                        box FieldElement::Identifier(Variable::field_element("a")),
+                        box FieldElement::Value(Bn128Field::from(3)),
                     ),
                 ),
                 Statement::Return(vec![box FieldElement::Add(
-                    box FieldElement::Identifier(Variable::with_name("b")),
-                    box FieldElement::Value(1),
+                    box FieldElement::Identifier(Variable::field_element("b")),
+                    box FieldElement::Value(Bn128Field::from(1)),
                 )]),
             ],
         };
@@ -147,15 +698,127 @@ mod test {
 
         println!("{}", flattened);
 
-        assert_eq!(flattened, FlatFunction::with_statements(vec![]));
+        assert_eq!(
+            flattened,
+            FlatFunction::new(
+                vec![],
+                vec![],
+                vec![FlatVariable::output(0)],
+                vec![
+                    FlatStatement::Directive(
+                        vec![FlatVariable::with_name("a_0")],
+                        Helper::Identity,
+                        vec![LinComb(vec![(Bn128Field::from(42), FlatVariable::one())])],
+                    ),
+                    FlatStatement::Assertion(
+                        LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_0"))]),
+                        LinComb(vec![(Bn128Field::from(1), FlatVariable::one())]),
+                        LinComb(vec![(Bn128Field::from(42), FlatVariable::one())]),
+                    ),
+                    FlatStatement::Directive(
+                        vec![FlatVariable::with_name("b_0")],
+                        Helper::Identity,
+                        vec![LinComb(vec![
+                            (Bn128Field::from(1), FlatVariable::with_name("a_0")),
+                            (Bn128Field::from(3), FlatVariable::one()),
+                        ])],
+                    ),
+                    FlatStatement::Assertion(
+                        LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("b_0"))]),
+                        LinComb(vec![(Bn128Field::from(1), FlatVariable::one())]),
+                        LinComb(vec![
+                            (Bn128Field::from(1), FlatVariable::with_name("a_0")),
+                            (Bn128Field::from(3), FlatVariable::one()),
+                        ]),
+                    ),
+                    FlatStatement::Directive(
+                        vec![FlatVariable::output(0)],
+                        Helper::Identity,
+                        vec![LinComb(vec![
+                            (Bn128Field::from(1), FlatVariable::with_name("b_0")),
+                            (Bn128Field::from(1), FlatVariable::one()),
+                        ])],
+                    ),
+                    FlatStatement::Assertion(
+                        LinComb(vec![(Bn128Field::from(1), FlatVariable::output(0))]),
+                        LinComb(vec![(Bn128Field::from(1), FlatVariable::one())]),
+                        LinComb(vec![
+                            (Bn128Field::from(1), FlatVariable::with_name("b_0")),
+                            (Bn128Field::from(1), FlatVariable::one()),
+                        ]),
+                    ),
+                    FlatStatement::Return(vec![FlatVariable::output(0)]),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn flatten_long_definition() {
+        // field[3] a = [42, 55, 66]
+        let f = Function {
+            id: "main".to_string(),
+            arguments: vec![],
+            returns: vec![],
+            statements: vec![Statement::Definition(
+                Variable::array("a", Type::FieldElement, 3),
+                box Array::value(vec![
+                    FieldElement::Value(Bn128Field::from(42)),
+                    FieldElement::Value(Bn128Field::from(55)),
+                    FieldElement::Value(Bn128Field::from(66)),
+                ]).unwrap(),
+            )],
+        };
+
+        let flattened = f.flatten();
+
+        assert_eq!(
+            flattened,
+            FlatFunction::with_statements(vec![
+                FlatStatement::Directive(
+                    vec![
+                        FlatVariable::with_name("a_0"),
+                        FlatVariable::with_name("a_1"),
+                        FlatVariable::with_name("a_2"),
+                    ],
+                    Helper::Identity,
+                    vec![
+                        LinComb(vec![(Bn128Field::from(42), FlatVariable::one())]),
+                        LinComb(vec![(Bn128Field::from(55), FlatVariable::one())]),
+                        LinComb(vec![(Bn128Field::from(66), FlatVariable::one())]),
+                    ],
+                ),
+                FlatStatement::Assertion(
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_0"))]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one())]),
+                    LinComb(vec![(Bn128Field::from(42), FlatVariable::one())]),
+                ),
+                FlatStatement::Assertion(
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_1"))]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one())]),
+                    LinComb(vec![(Bn128Field::from(55), FlatVariable::one())]),
+                ),
+                FlatStatement::Assertion(
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_2"))]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one())]),
+                    LinComb(vec![(Bn128Field::from(66), FlatVariable::one())]),
+                ),
+            ])
+        );
     }
 
     #[test]
     fn flatten_definition() {
         let f2 = Function {
+            id: "main".to_string(),
+            arguments: vec![],
+            returns: vec![],
             statements: vec![Statement::Definition(
-                Variable::with_name("a"),
-                box Array::Value(vec![FieldElement::Value(42), FieldElement::Value(55)]),
+                Variable::array("a", Type::FieldElement, 2),
+                box Array::Value(vec![
+                    FieldElement::Value(Bn128Field::from(42)),
+                    FieldElement::Value(Bn128Field::from(55)),
+                ]),
             )],
         };
 
@@ -166,19 +829,26 @@ mod test {
         assert_eq!(
             flattened,
             FlatFunction::with_statements(vec![
-                FlatStatement::Directive(vec![
-                    FlatVariable::with_name("a_0"),
-                    FlatVariable::with_name("a_1")
-                ]),
+                FlatStatement::Directive(
+                    vec![
+                        FlatVariable::with_name("a_0"),
+                        FlatVariable::with_name("a_1")
+                    ],
+                    Helper::Identity,
+                    vec![
+                        LinComb(vec![(Bn128Field::from(42), FlatVariable::one())]),
+                        LinComb(vec![(Bn128Field::from(55), FlatVariable::one())]),
+                    ],
+                ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_0"))]),
-                    LinComb(vec![(1, FlatVariable::one())]),
-                    LinComb(vec![(42, FlatVariable::one())]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_0"))]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one())]),
+                    LinComb(vec![(Bn128Field::from(42), FlatVariable::one())]),
                 ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_1"))]),
-                    LinComb(vec![(1, FlatVariable::one())]),
-                    LinComb(vec![(55, FlatVariable::one())]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_1"))]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one())]),
+                    LinComb(vec![(Bn128Field::from(55), FlatVariable::one())]),
                 )
             ])
         );
@@ -195,11 +865,20 @@ mod test {
         // (1 * a_3) * (1 * one) == (55 * one)
 
         let f2 = Function {
+            id: "main".to_string(),
+            arguments: vec![],
+            returns: vec![],
             statements: vec![Statement::Definition(
-                Variable::with_name("a"),
+                Variable::array("a", Type::Array(box Type::FieldElement, 2), 2),
                 box Array::Value(vec![
-                    Array::Value(vec![FieldElement::Value(42), FieldElement::Value(55)]),
-                    Array::Value(vec![FieldElement::Value(42), FieldElement::Value(55)]),
+                    Array::Value(vec![
+                        FieldElement::Value(Bn128Field::from(42)),
+                        FieldElement::Value(Bn128Field::from(55)),
+                    ]),
+                    Array::Value(vec![
+                        FieldElement::Value(Bn128Field::from(42)),
+                        FieldElement::Value(Bn128Field::from(55)),
+                    ]),
                 ]),
             )],
         };
@@ -211,31 +890,40 @@ mod test {
         assert_eq!(
             flattened,
             FlatFunction::with_statements(vec![
-                FlatStatement::Directive(vec![
-                    FlatVariable::with_name("a_0"),
-                    FlatVariable::with_name("a_1"),
-                    FlatVariable::with_name("a_2"),
-                    FlatVariable::with_name("a_3"),
-                ]),
+                FlatStatement::Directive(
+                    vec![
+                        FlatVariable::with_name("a_0"),
+                        FlatVariable::with_name("a_1"),
+                        FlatVariable::with_name("a_2"),
+                        FlatVariable::with_name("a_3"),
+                    ],
+                    Helper::Identity,
+                    vec![
+                        LinComb(vec![(Bn128Field::from(42), FlatVariable::one(),)]),
+                        LinComb(vec![(Bn128Field::from(55), FlatVariable::one(),)]),
+                        LinComb(vec![(Bn128Field::from(42), FlatVariable::one(),)]),
+                        LinComb(vec![(Bn128Field::from(55), FlatVariable::one(),)]),
+                    ],
+                ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_0"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(42, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_0"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(42), FlatVariable::one(),)]),
                 ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_1"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(55, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_1"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(55), FlatVariable::one(),)]),
                 ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_2"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(42, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_2"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(42), FlatVariable::one(),)]),
                 ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_3"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(55, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_3"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(55), FlatVariable::one(),)]),
                 ),
             ])
         );
@@ -252,16 +940,35 @@ mod test {
         // (1 * a_3) * (1 * one) == (55 * one)
 
         let f2 = Function {
+            id: "main".to_string(),
+            arguments: vec![],
+            returns: vec![],
             statements: vec![Statement::Definition(
-                Variable::with_name("a"),
+                Variable::array(
+                    "a",
+                    Type::Array(box Type::Array(box Type::FieldElement, 2), 2),
+                    2,
+                ),
                 box Array::Value(vec![
                     Array::Value(vec![
-                        Array::Value(vec![FieldElement::Value(42), FieldElement::Value(55)]),
-                        Array::Value(vec![FieldElement::Value(42), FieldElement::Value(55)]),
+                        Array::Value(vec![
+                            FieldElement::Value(Bn128Field::from(42)),
+                            FieldElement::Value(Bn128Field::from(55)),
+                        ]),
+                        Array::Value(vec![
+                            FieldElement::Value(Bn128Field::from(42)),
+                            FieldElement::Value(Bn128Field::from(55)),
+                        ]),
                     ]),
                     Array::Value(vec![
-                        Array::Value(vec![FieldElement::Value(42), FieldElement::Value(55)]),
-                        Array::Value(vec![FieldElement::Value(42), FieldElement::Value(55)]),
+                        Array::Value(vec![
+                            FieldElement::Value(Bn128Field::from(42)),
+                            FieldElement::Value(Bn128Field::from(55)),
+                        ]),
+                        Array::Value(vec![
+                            FieldElement::Value(Bn128Field::from(42)),
+                            FieldElement::Value(Bn128Field::from(55)),
+                        ]),
                     ]),
                 ]),
             )],
@@ -274,55 +981,68 @@ mod test {
         assert_eq!(
             flattened,
             FlatFunction::with_statements(vec![
-                FlatStatement::Directive(vec![
-                    FlatVariable::with_name("a_0"),
-                    FlatVariable::with_name("a_1"),
-                    FlatVariable::with_name("a_2"),
-                    FlatVariable::with_name("a_3"),
-                    FlatVariable::with_name("a_4"),
-                    FlatVariable::with_name("a_5"),
-                    FlatVariable::with_name("a_6"),
-                    FlatVariable::with_name("a_7"),
-                ]),
+                FlatStatement::Directive(
+                    vec![
+                        FlatVariable::with_name("a_0"),
+                        FlatVariable::with_name("a_1"),
+                        FlatVariable::with_name("a_2"),
+                        FlatVariable::with_name("a_3"),
+                        FlatVariable::with_name("a_4"),
+                        FlatVariable::with_name("a_5"),
+                        FlatVariable::with_name("a_6"),
+                        FlatVariable::with_name("a_7"),
+                    ],
+                    Helper::Identity,
+                    vec![
+                        LinComb(vec![(Bn128Field::from(42), FlatVariable::one(),)]),
+                        LinComb(vec![(Bn128Field::from(55), FlatVariable::one(),)]),
+                        LinComb(vec![(Bn128Field::from(42), FlatVariable::one(),)]),
+                        LinComb(vec![(Bn128Field::from(55), FlatVariable::one(),)]),
+                        LinComb(vec![(Bn128Field::from(42), FlatVariable::one(),)]),
+                        LinComb(vec![(Bn128Field::from(55), FlatVariable::one(),)]),
+                        LinComb(vec![(Bn128Field::from(42), FlatVariable::one(),)]),
+                        LinComb(vec![(Bn128Field::from(55), FlatVariable::one(),)]),
+                    ],
+                ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_0"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(42, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_0"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(42), FlatVariable::one(),)]),
                 ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_1"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(55, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_1"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(55), FlatVariable::one(),)]),
                 ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_2"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(42, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_2"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(42), FlatVariable::one(),)]),
                 ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_3"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(55, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_3"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(55), FlatVariable::one(),)]),
                 ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_4"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(42, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_4"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(42), FlatVariable::one(),)]),
                 ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_5"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(55, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_5"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(55), FlatVariable::one(),)]),
                 ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_6"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(42, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_6"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(42), FlatVariable::one(),)]),
                 ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_7"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(55, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_7"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(55), FlatVariable::one(),)]),
                 ),
             ])
         );
@@ -336,11 +1056,24 @@ mod test {
         // # a_0, a_1 := ... // this will be a directive, could also be multiple..
         // (1 * a_0) * (1 * one) == (42 * one)
         // (1 * a_1) * (1 * one) == (1 * one)
+        // (1 * a_1) * (1 * one + -1 * a_1) == (0 * one) // a_1 is a bit
 
         let f2 = Function {
+            id: "main".to_string(),
+            arguments: vec![],
+            returns: vec![],
             statements: vec![Statement::Definition(
-                Variable::with_name("a"),
-                box Structure::Value(vec![box FieldElement::Value(42), box Boolean::Value(true)]),
+                Variable::structure(
+                    "a",
+                    vec![
+                        ("foo".to_string(), Type::FieldElement),
+                        ("bar".to_string(), Type::Boolean),
+                    ],
+                ),
+                box Structure::Value(vec![
+                    ("foo".to_string(), box FieldElement::Value(Bn128Field::from(42))),
+                    ("bar".to_string(), box Boolean::Value(true)),
+                ]),
             )],
         };
 
@@ -351,19 +1084,34 @@ mod test {
         assert_eq!(
             flattened,
             FlatFunction::with_statements(vec![
-                FlatStatement::Directive(vec![
-                    FlatVariable::with_name("a_0"),
-                    FlatVariable::with_name("a_1"),
-                ]),
+                FlatStatement::Directive(
+                    vec![
+                        FlatVariable::with_name("a_0"),
+                        FlatVariable::with_name("a_1"),
+                    ],
+                    Helper::Identity,
+                    vec![
+                        LinComb(vec![(Bn128Field::from(42), FlatVariable::one(),)]),
+                        LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    ],
+                ),
+                FlatStatement::Assertion(
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_0"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(42), FlatVariable::one(),)]),
+                ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_0"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(42, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_1"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
                 ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_1"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_1"),)]),
+                    LinComb(vec![
+                        (Bn128Field::from(1), FlatVariable::one()),
+                        (-Bn128Field::from(1), FlatVariable::with_name("a_1")),
+                    ]),
+                    LinComb::constant(Bn128Field::from(0)),
                 ),
             ])
         );
@@ -379,16 +1127,41 @@ mod test {
         // (1 * a_1) * (1 * one) == (21 * one)
         // (1 * a_0) * (1 * one) == (21 * one)
         // (1 * a_1) * (1 * one) == (1 * one)
+        // (1 * a_3) * (1 * one + -1 * a_3) == (0 * one) // a_3 is a bit
 
         let f2 = Function {
+            id: "main".to_string(),
+            arguments: vec![],
+            returns: vec![],
             statements: vec![Statement::Definition(
-                Variable::with_name("a"),
+                Variable::structure(
+                    "a",
+                    vec![
+                        (
+                            "foo".to_string(),
+                            Type::Struct(vec![
+                                ("baz".to_string(), Type::FieldElement),
+                                ("qux".to_string(), Type::Array(box Type::FieldElement, 2)),
+                            ]),
+                        ),
+                        ("bar".to_string(), Type::Boolean),
+                    ],
+                ),
                 box Structure::Value(vec![
-                    box Structure::Value(vec![
-                        box FieldElement::Value(42),
-                        box Array::Value(vec![FieldElement::Value(21), FieldElement::Value(21)]),
-                    ]),
-                    box Boolean::Value(true),
+                    (
+                        "foo".to_string(),
+                        box Structure::Value(vec![
+                            ("baz".to_string(), box FieldElement::Value(Bn128Field::from(42))),
+                            (
+                                "qux".to_string(),
+                                box Array::Value(vec![
+                                    FieldElement::Value(Bn128Field::from(21)),
+                                    FieldElement::Value(Bn128Field::from(21)),
+                                ]),
+                            ),
+                        ]),
+                    ),
+                    ("bar".to_string(), box Boolean::Value(true)),
                 ]),
             )],
         };
@@ -400,33 +1173,1443 @@ mod test {
         assert_eq!(
             flattened,
             FlatFunction::with_statements(vec![
-                FlatStatement::Directive(vec![
-                    FlatVariable::with_name("a_0"),
-                    FlatVariable::with_name("a_1"),
-                    FlatVariable::with_name("a_2"),
-                    FlatVariable::with_name("a_3"),
-                ]),
+                FlatStatement::Directive(
+                    vec![
+                        FlatVariable::with_name("a_0"),
+                        FlatVariable::with_name("a_1"),
+                        FlatVariable::with_name("a_2"),
+                        FlatVariable::with_name("a_3"),
+                    ],
+                    Helper::Identity,
+                    vec![
+                        LinComb(vec![(Bn128Field::from(42), FlatVariable::one(),)]),
+                        LinComb(vec![(Bn128Field::from(21), FlatVariable::one(),)]),
+                        LinComb(vec![(Bn128Field::from(21), FlatVariable::one(),)]),
+                        LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    ],
+                ),
+                FlatStatement::Assertion(
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_0"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(42), FlatVariable::one(),)]),
+                ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_0"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(42, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_1"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(21), FlatVariable::one(),)]),
                 ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_1"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(21, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_2"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(21), FlatVariable::one(),)]),
                 ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_2"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(21, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_3"),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one(),)]),
                 ),
                 FlatStatement::Assertion(
-                    LinComb(vec![(1, FlatVariable::with_name("a_3"),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
-                    LinComb(vec![(1, FlatVariable::one(),)]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::with_name("a_3"),)]),
+                    LinComb(vec![
+                        (Bn128Field::from(1), FlatVariable::one()),
+                        (-Bn128Field::from(1), FlatVariable::with_name("a_3")),
+                    ]),
+                    LinComb::constant(Bn128Field::from(0)),
                 ),
             ])
         );
     }
+
+    #[test]
+    fn type_size() {
+        // {foo: { baz: field, qux: field[2] }, bar: bool[3]}
+        let t = Type::Struct(vec![
+            (
+                "foo".to_string(),
+                Type::Struct(vec![
+                    ("baz".to_string(), Type::FieldElement),
+                    ("qux".to_string(), Type::Array(box Type::FieldElement, 2)),
+                ]),
+            ),
+            ("bar".to_string(), Type::Array(box Type::Boolean, 3)),
+        ]);
+
+        assert_eq!(t.size(), 6);
+        assert_eq!(Type::Array(box t, 4).size(), 24);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot bind 2 slots to 1 values")]
+    fn bind_wrong_count() {
+        let mut flattener: Flattener<Bn128Field> = Flattener::new();
+        let vars = flattener.declare(&Variable::array("a", Type::FieldElement, 2));
+        flattener.bind(&vars, vec![LinComb::constant(Bn128Field::from(1))]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot compare 2 slots to 1 slots")]
+    fn assert_eq_wrong_count() {
+        let a: Array<Bn128Field, FieldElement<Bn128Field>> =
+            Array::Identifier(Variable::array("a", Type::FieldElement, 2));
+        let s = Statement::AssertEq(box a, box FieldElement::Value(Bn128Field::from(1)));
+        s.flatten(&mut Flattener::new());
+    }
+
+    #[test]
+    fn flatten_identifier() {
+        // field[2][3] a
+        let a: Array<Bn128Field, Array<Bn128Field, FieldElement<Bn128Field>>> =
+            Array::Identifier(Variable::array("a", Type::Array(box Type::FieldElement, 2), 3));
+
+        let mut flattener = Flattener::new();
+
+        assert_eq!(
+            a.flatten(&mut flattener),
+            (0..6)
+                .map(|i| LinComb::from(FlatVariable::with_name(format!("a_{}", i))))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(flattener.statements(), &vec![]);
+    }
+
+    #[test]
+    fn structure_expression() {
+        let s = Structure::value(vec![
+            ("foo".to_string(), box FieldElement::Value(Bn128Field::from(42))),
+            ("bar".to_string(), box Boolean::Value(true)),
+        ]).unwrap();
+
+        assert_eq!(s.to_string(), "{foo: 42, bar: true}");
+        assert_eq!(
+            s.get_type(),
+            Type::Struct(vec![
+                ("foo".to_string(), Type::FieldElement),
+                ("bar".to_string(), Type::Boolean),
+            ])
+        );
+    }
+
+    #[test]
+    fn duplicate_member() {
+        assert!(
+            Structure::value(vec![
+                ("foo".to_string(), box FieldElement::Value(Bn128Field::from(42))),
+                ("foo".to_string(), box Boolean::Value(true)),
+            ]).is_err()
+        );
+    }
+
+    #[test]
+    fn flatten_member_access() {
+        // {foo: { baz: field, qux: field[2] }, bar: bool} a
+        let a = || {
+            Structure::Identifier(Variable::structure(
+                "a",
+                vec![
+                    (
+                        "foo".to_string(),
+                        Type::Struct(vec![
+                            ("baz".to_string(), Type::FieldElement),
+                            ("qux".to_string(), Type::Array(box Type::FieldElement, 2)),
+                        ]),
+                    ),
+                    ("bar".to_string(), Type::Boolean),
+                ],
+            ))
+        };
+
+        // a.foo.qux
+        let qux: Array<Bn128Field, FieldElement<Bn128Field>> = Array::Member(
+            box Structure::Member(box a(), "foo".to_string()),
+            "qux".to_string(),
+        );
+
+        assert_eq!(qux.to_string(), "a.foo.qux");
+        assert_eq!(qux.get_type(), Type::Array(box Type::FieldElement, 2));
+
+        let mut flattener = Flattener::new();
+
+        assert_eq!(
+            qux.flatten(&mut flattener),
+            vec![
+                LinComb::from(FlatVariable::with_name("a_1")),
+                LinComb::from(FlatVariable::with_name("a_2")),
+            ]
+        );
+
+        // a.bar
+        assert_eq!(
+            Boolean::Member(box a(), "bar".to_string()).flatten(&mut flattener),
+            vec![LinComb::from(FlatVariable::with_name("a_3"))]
+        );
+    }
+
+    #[test]
+    fn flatten_tuple() {
+        // (field, bool[2]) t
+        let t = || {
+            Tuple::Identifier(Variable::tuple(
+                "t",
+                vec![Type::FieldElement, Type::Array(box Type::Boolean, 2)],
+            ))
+        };
+
+        // t.1
+        let e: Array<Bn128Field, Boolean<Bn128Field>> = Array::Element(box t(), 1);
+
+        assert_eq!(e.to_string(), "t.1");
+        assert_eq!(e.get_type(), Type::Array(box Type::Boolean, 2));
+        assert_eq!(
+            e.flatten(&mut Flattener::new()),
+            vec![
+                LinComb::from(FlatVariable::with_name("t_1")),
+                LinComb::from(FlatVariable::with_name("t_2")),
+            ]
+        );
+
+        // field a, bool[2] b = (2, t.1)
+        let f = Function::with_statements(vec![Statement::MultipleDefinition(
+            vec![
+                Variable::field_element("a"),
+                Variable::array("b", Type::Boolean, 2),
+            ],
+            box Tuple::value(vec![
+                box FieldElement::Value(Bn128Field::from(2)),
+                box Array::<Bn128Field, Boolean<Bn128Field>>::Element(box t(), 1),
+            ]).unwrap(),
+        )]);
+
+        // each variable takes the slots of its element
+        assert_eq!(
+            f.flatten().to_string(),
+            vec![
+                "# a_0 := Identity(2 * ~one)",
+                "(1 * a_0) * (1 * ~one) == (2 * ~one)",
+                "# b_0, b_1 := Identity(1 * t_1, 1 * t_2)",
+                "(1 * b_0) * (1 * ~one) == (1 * t_1)",
+                "(1 * b_1) * (1 * ~one) == (1 * t_2)",
+                "(1 * b_0) * (1 * ~one + -1 * b_0) == (0 * ~one)",
+                "(1 * b_1) * (1 * ~one + -1 * b_1) == (0 * ~one)",
+            ].join("\n")
+        );
+    }
+
+    #[test]
+    fn flatten_match() {
+        // enum Op { Add(field), Swap(field[2]) }
+        let op = Type::Enum(
+            "Op".to_string(),
+            vec![
+                ("Add".to_string(), Type::FieldElement),
+                ("Swap".to_string(), Type::Array(box Type::FieldElement, 2)),
+            ],
+        );
+        assert_eq!(op.size(), 3);
+
+        // match e { Add(a) => a, Swap(b) => b[1] }
+        let m = |e| -> FieldElement<Bn128Field> {
+            FieldElement::Match(
+                box e,
+                vec![
+                    MatchArm {
+                        variant: "Add".to_string(),
+                        binding: Variable::field_element("a"),
+                        expression: box FieldElement::Identifier(Variable::field_element("a")),
+                    },
+                    MatchArm {
+                        variant: "Swap".to_string(),
+                        binding: Variable::array("b", Type::FieldElement, 2),
+                        expression: box FieldElement::Select(
+                            box Array::Identifier(Variable::array("b", Type::FieldElement, 2)),
+                            box FieldElement::Value(Bn128Field::from(1)),
+                        ),
+                    },
+                ],
+            )
+        };
+
+        // a constant tag picks its arm
+        let swap = Enumeration::variant(
+            op.clone(),
+            "Swap".to_string(),
+            box Array::Value(vec![
+                FieldElement::Value(Bn128Field::from(3)),
+                FieldElement::Value(Bn128Field::from(4)),
+            ]),
+        ).unwrap();
+        assert_eq!(swap.to_string(), "Op::Swap([3, 4])");
+        assert_eq!(
+            m(swap).flatten(&mut Flattener::new()),
+            vec![LinComb::constant(Bn128Field::from(4))]
+        );
+
+        let m = m(Enumeration::Identifier(Variable::new("e", op)));
+        assert_eq!(m.to_string(), "match e { Add(a) => a, Swap(b) => b[1] }");
+
+        let mut flattener = Flattener::new();
+
+        // each arm reads the slots of its payload, its result is masked by whether the tag is its
+        // own and the results are summed up. Exactly one tag must match
+        assert_eq!(
+            m.flatten(&mut flattener),
+            vec![LinComb::from(FlatVariable::with_name("~2"))
+                + LinComb::from(FlatVariable::with_name("~5"))]
+        );
+        assert_eq!(
+            FlatFunction::with_statements(flattener.into_statements()).to_string(),
+            vec![
+                "# ~0 := Inverse(1 * e_0 + 0 * ~one)",
+                "# ~1 := Product(1 * e_0 + 0 * ~one, 1 * ~0)",
+                "(1 * e_0 + 0 * ~one) * (1 * ~0) == (1 * ~1)",
+                "(1 * e_0 + 0 * ~one) * (1 * ~one + -1 * ~1) == (0 * ~one)",
+                "# ~2 := Product(1 * ~one + -1 * ~1, 1 * e_1)",
+                "(1 * ~one + -1 * ~1) * (1 * e_1) == (1 * ~2)",
+                "# ~3 := Inverse(1 * e_0 + -1 * ~one)",
+                "# ~4 := Product(1 * e_0 + -1 * ~one, 1 * ~3)",
+                "(1 * e_0 + -1 * ~one) * (1 * ~3) == (1 * ~4)",
+                "(1 * e_0 + -1 * ~one) * (1 * ~one + -1 * ~4) == (0 * ~one)",
+                "# ~5 := Product(1 * ~one + -1 * ~4, 1 * e_2)",
+                "(1 * ~one + -1 * ~4) * (1 * e_2) == (1 * ~5)",
+                "(2 * ~one + -1 * ~1 + -1 * ~4) * (1 * ~one) == (1 * ~one)",
+            ].join("\n")
+        );
+    }
+
+    #[test]
+    fn flatten_enum_checks() {
+        // enum E { A(bool), B(field[2]) }, the first payload slot being a field element
+        let ty = Type::Enum(
+            "E".to_string(),
+            vec![
+                ("A".to_string(), Type::Boolean),
+                ("B".to_string(), Type::Array(box Type::FieldElement, 2)),
+            ],
+        );
+        assert_eq!(ty.flat_types(), vec![Type::FieldElement; 3]);
+
+        // def main(E e) -> (bool) { return match e { A(a) => a, B(b) => false } }
+        let f: Function<Bn128Field> = Function::new(
+            "main",
+            vec![Parameter::public(Variable::new("e", ty.clone()))],
+            vec![Type::Boolean],
+            vec![Statement::Return(vec![box Boolean::Match(
+                box Enumeration::Identifier(Variable::new("e", ty)),
+                vec![
+                    MatchArm {
+                        variant: "A".to_string(),
+                        binding: Variable::boolean("a"),
+                        expression: box Boolean::Identifier(Variable::boolean("a")),
+                    },
+                    MatchArm {
+                        variant: "B".to_string(),
+                        binding: Variable::array("b", Type::FieldElement, 2),
+                        expression: box Boolean::Value(false),
+                    },
+                ],
+            )])],
+        );
+
+        let flattened = f.flatten().to_string();
+
+        // the second payload slot is zero unless the tag is that of B
+        assert!(flattened.contains("(0 * ~one + 1 * ~3) * (1 * e_2) == (0 * ~one)"));
+        // the payload of A is a bit when the tag is its own
+        assert!(flattened.contains("# ~6 := Product(1 * e_1, 1 * ~one + -1 * e_1)"));
+        assert!(flattened.contains("(1 * e_1) * (1 * ~one + -1 * e_1) == (1 * ~6)"));
+        assert!(flattened.contains("(1 * ~one + -1 * ~5) * (1 * ~6 + 0 * ~one) == (0 * ~one)"));
+    }
+
+    // runs the directives of `statements` in order, starting from `inputs`, and returns the value
+    // of every variable if all the assertions then hold
+    fn solve(
+        statements: &[FlatStatement<Bn128Field>],
+        inputs: Vec<(&str, Bn128Field)>,
+    ) -> Option<HashMap<String, Bn128Field>> {
+        use num_bigint::BigUint;
+
+        let mut values: HashMap<_, _> =
+            inputs.into_iter().map(|(v, x)| (v.to_string(), x)).collect();
+        values.insert(FlatVariable::one().to_string(), Bn128Field::from(1));
+        let evaluate = |values: &HashMap<String, Bn128Field>, l: &LinComb<Bn128Field>| {
+            l.0.iter().fold(Bn128Field::from(0), |acc, &(ref c, ref v)| {
+                acc + c.clone() * values[&v.to_string()].clone()
+            })
+        };
+
+        for s in statements {
+            match *s {
+                FlatStatement::Directive(ref outputs, ref helper, ref inputs) => {
+                    let inputs: Vec<_> = inputs.iter().map(|i| evaluate(&values, i)).collect();
+                    let bit = |b: bool| Bn128Field::from(b as usize);
+                    let results = match *helper {
+                        Helper::Identity => inputs,
+                        Helper::Product => vec![inputs[0].clone() * inputs[1].clone()],
+                        Helper::Inverse => {
+                            vec![inputs[0].inverse_mul().unwrap_or(Bn128Field::from(0))]
+                        }
+                        Helper::Bits => {
+                            let v = inputs[0].to_biguint();
+                            (0..outputs.len())
+                                .map(|i| bit((&v >> i) % 2u32 == BigUint::from(1u32)))
+                                .collect()
+                        }
+                        Helper::Selector => (0..outputs.len())
+                            .map(|i| bit(inputs[0] == Bn128Field::from(i)))
+                            .collect(),
+                    };
+                    for (v, x) in outputs.iter().zip(results) {
+                        values.insert(v.to_string(), x);
+                    }
+                }
+                FlatStatement::Assertion(ref a, ref b, ref c) => {
+                    if evaluate(&values, a) * evaluate(&values, b) != evaluate(&values, c) {
+                        return None;
+                    }
+                }
+                FlatStatement::Return(..) => {}
+            }
+        }
+
+        Some(values)
+    }
+
+    #[test]
+    fn flatten_match_witness() {
+        // enum E { A(field), B(field) }
+        let ty = Type::Enum(
+            "E".to_string(),
+            vec![
+                ("A".to_string(), Type::FieldElement),
+                ("B".to_string(), Type::FieldElement),
+            ],
+        );
+        let x = || box FieldElement::Identifier(Variable::field_element("x"));
+        let y = || box FieldElement::Identifier(Variable::field_element("y"));
+
+        // def main(E e) -> (field) { return match e { A(x) => 1 / x, B(y) => y } }
+        let f: Function<Bn128Field> = Function::new(
+            "main",
+            vec![Parameter::public(Variable::new("e", ty.clone()))],
+            vec![Type::FieldElement],
+            vec![Statement::Return(vec![box FieldElement::Match(
+                box Enumeration::Identifier(Variable::new("e", ty)),
+                vec![
+                    MatchArm {
+                        variant: "A".to_string(),
+                        binding: Variable::field_element("x"),
+                        expression: box FieldElement::Div(
+                            box FieldElement::Value(Bn128Field::from(1)),
+                            x(),
+                        ),
+                    },
+                    MatchArm {
+                        variant: "B".to_string(),
+                        binding: Variable::field_element("y"),
+                        expression: y(),
+                    },
+                ],
+            )])],
+        );
+
+        let flattened = f.flatten();
+        let e = |tag: usize, payload: usize| {
+            vec![("e_0", Bn128Field::from(tag)), ("e_1", Bn128Field::from(payload))]
+        };
+        let output = |inputs| {
+            solve(flattened.statements(), inputs).map(|values| values["~out_0"].clone())
+        };
+
+        // the arm of A cannot divide by zero, but only has to when it is selected
+        assert_eq!(output(e(1, 0)), Some(Bn128Field::from(0)));
+        assert_eq!(output(e(1, 3)), Some(Bn128Field::from(3)));
+        assert_eq!(output(e(0, 4)), Some(Bn128Field::from(1) / Bn128Field::from(4)));
+        assert_eq!(output(e(0, 0)), None);
+    }
+
+    #[test]
+    fn flatten_option() {
+        let value = |v| box FieldElement::Value(Bn128Field::from(v));
+
+        // None.unwrap_or(3), Some(2).is_some()
+        let mut flattener = Flattener::new();
+        assert_eq!(
+            FieldElement::UnwrapOr(box Optional::None(Type::FieldElement), value(3))
+                .flatten(&mut flattener),
+            vec![LinComb::constant(Bn128Field::from(3))]
+        );
+        assert_eq!(
+            Boolean::IsSome(box Optional::Some(value(2))).flatten(&mut flattener),
+            vec![LinComb::constant(Bn128Field::from(1))]
+        );
+        assert_eq!(flattener.statements(), &vec![]);
+
+        // def main(private Option<bool> o) -> (bool) { return o.unwrap_or(true) }
+        let o = Variable::new("o", Type::Option(box Type::Boolean));
+        let f = Function::<Bn128Field>::new(
+            "main",
+            vec![Parameter::private(o.clone())],
+            vec![Type::Boolean],
+            vec![Statement::Return(vec![box Boolean::UnwrapOr(
+                box Optional::Identifier(o),
+                box Boolean::Value(true),
+            )])],
+        );
+
+        // an absent value must be zero
+        assert_eq!(
+            f.flatten().to_string(),
+            vec![
+                "(1 * o_0) * (1 * ~one + -1 * o_0) == (0 * ~one)",
+                "(1 * o_1) * (1 * ~one + -1 * o_1) == (0 * ~one)",
+                "(1 * ~one + -1 * o_0) * (1 * o_1) == (0 * ~one)",
+                "# ~0 := Product(1 * o_0, 1 * o_1 + -1 * ~one)",
+                "(1 * o_0) * (1 * o_1 + -1 * ~one) == (1 * ~0)",
+                "# ~out_0 := Identity(1 * ~0 + 1 * ~one)",
+                "(1 * ~out_0) * (1 * ~one) == (1 * ~0 + 1 * ~one)",
+                "return ~out_0",
+            ].join("\n")
+        );
+    }
+
+    #[test]
+    fn flatten_option_in_enum() {
+        // enum E { A(Option<field>), B(field) } e
+        let ty = Type::Enum(
+            "E".to_string(),
+            vec![
+                ("A".to_string(), Type::Option(box Type::FieldElement)),
+                ("B".to_string(), Type::FieldElement),
+            ],
+        );
+        let mut flattener: Flattener<Bn128Field> = Flattener::new();
+        let vars = flattener.declare(&Variable::new("e", ty.clone()));
+        flattener.assert_bits(&Flattener::slots(&vars), &ty);
+
+        let statements: Vec<_> = flattener.statements().iter().map(|s| s.to_string()).collect();
+
+        // the payload of A is masked by whether the tag is its own, and its value is zero when
+        // the masked flag is not set
+        assert_eq!(
+            &statements[statements.len() - 5..],
+            &[
+                "# ~4 := Product(1 * ~one + -1 * ~1, 1 * e_1)",
+                "(1 * ~one + -1 * ~1) * (1 * e_1) == (1 * ~4)",
+                "# ~5 := Product(1 * ~one + -1 * ~1, 1 * e_2)",
+                "(1 * ~one + -1 * ~1) * (1 * e_2) == (1 * ~5)",
+                "(1 * ~one + -1 * ~4) * (1 * ~5) == (0 * ~one)",
+            ]
+        );
+    }
+
+    #[test]
+    fn flatten_constant_select() {
+        // field[3][2] a
+        let a = || -> Array<Bn128Field, Array<Bn128Field, FieldElement<Bn128Field>>> {
+            Array::Identifier(Variable::array("a", Type::Array(box Type::FieldElement, 3), 2))
+        };
+
+        let mut flattener = Flattener::new();
+
+        // a[1]
+        let a1: Array<Bn128Field, FieldElement<Bn128Field>> =
+            Array::Select(box a(), box FieldElement::Value(Bn128Field::from(1)));
+        assert_eq!(a1.get_type(), Type::Array(box Type::FieldElement, 3));
+        assert_eq!(
+            a1.flatten(&mut flattener),
+            vec![
+                LinComb::from(FlatVariable::with_name("a_3")),
+                LinComb::from(FlatVariable::with_name("a_4")),
+                LinComb::from(FlatVariable::with_name("a_5")),
+            ]
+        );
+
+        // a[1][2]
+        let a12 = FieldElement::Select(
+            box Array::Select(box a(), box FieldElement::Value(Bn128Field::from(1))),
+            box FieldElement::Value(Bn128Field::from(2)),
+        );
+        assert_eq!(a12.to_string(), "a[1][2]");
+        assert_eq!(
+            a12.flatten(&mut flattener),
+            vec![LinComb::from(FlatVariable::with_name("a_5"))]
+        );
+
+        assert_eq!(flattener.statements(), &vec![]);
+    }
+
+    #[test]
+    fn flatten_constant_select_structure() {
+        // {foo: field, bar: bool}[2] a
+        let a = Array::Identifier(Variable::array(
+            "a",
+            Type::Struct(vec![
+                ("foo".to_string(), Type::FieldElement),
+                ("bar".to_string(), Type::Boolean),
+            ]),
+            2,
+        ));
+
+        // a[1].bar
+        let e = Boolean::Member(
+            box Structure::Select(box a, box FieldElement::Value(Bn128Field::from(1))),
+            "bar".to_string(),
+        );
+
+        let mut flattener = Flattener::new();
+
+        assert_eq!(
+            e.flatten(&mut flattener),
+            vec![LinComb::from(FlatVariable::with_name("a_3"))]
+        );
+    }
+
+    #[test]
+    fn flatten_constant_select_out_of_bounds() {
+        // a[if true then 2 else 0], with field[2] a
+        let a: Array<Bn128Field, FieldElement<Bn128Field>> =
+            Array::Identifier(Variable::array("a", Type::FieldElement, 2));
+        let e = FieldElement::Select(
+            box a,
+            box FieldElement::IfElse(
+                box Boolean::Value(true),
+                box FieldElement::Value(Bn128Field::from(2)),
+                box FieldElement::Value(Bn128Field::from(0)),
+            ),
+        );
+        let mut flattener = Flattener::new();
+
+        // the index is reported, and zeros stand for the element
+        assert_eq!(e.flatten(&mut flattener), vec![LinComb::constant(Bn128Field::from(0))]);
+        assert_eq!(
+            flattener.errors(),
+            &vec!["index 2 out of bounds for a of type field[2]".to_string()]
+        );
+    }
+
+    #[test]
+    fn flatten_variable_select() {
+        // field[2] a, field i
+        // a[i]
+        let e = FieldElement::Select(
+            box Array::Identifier(Variable::array("a", Type::FieldElement, 2)),
+            box FieldElement::Identifier(Variable::field_element("i")),
+        );
+
+        let mut flattener = Flattener::new();
+
+        let flattened = e.flatten(&mut flattener);
+
+        let v = |name| FlatVariable::with_name(name);
+
+        assert_eq!(
+            flattened,
+            vec![LinComb(vec![
+                (Bn128Field::from(1), v("~2")),
+                (Bn128Field::from(1), v("~3")),
+            ])]
+        );
+        assert_eq!(
+            flattener.into_statements(),
+            vec![
+                FlatStatement::Directive(
+                    vec![v("~0"), v("~1")],
+                    Helper::Selector,
+                    vec![LinComb::from(v("i_0"))],
+                ),
+                FlatStatement::Assertion(
+                    LinComb::from(v("~0")),
+                    LinComb::constant(Bn128Field::from(1)) - LinComb::from(v("~0")),
+                    LinComb::constant(Bn128Field::from(0)),
+                ),
+                FlatStatement::Assertion(
+                    LinComb::from(v("~0")),
+                    LinComb::from(v("i_0")),
+                    LinComb(vec![(Bn128Field::from(0), v("~0"))]),
+                ),
+                FlatStatement::Assertion(
+                    LinComb::from(v("~1")),
+                    LinComb::constant(Bn128Field::from(1)) - LinComb::from(v("~1")),
+                    LinComb::constant(Bn128Field::from(0)),
+                ),
+                FlatStatement::Assertion(
+                    LinComb::from(v("~1")),
+                    LinComb::from(v("i_0")),
+                    LinComb(vec![(Bn128Field::from(1), v("~1"))]),
+                ),
+                FlatStatement::Assertion(
+                    LinComb(vec![(Bn128Field::from(1), v("~0")), (Bn128Field::from(1), v("~1"))]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one())]),
+                    LinComb(vec![(Bn128Field::from(1), FlatVariable::one())]),
+                ),
+                FlatStatement::Directive(
+                    vec![v("~2")],
+                    Helper::Product,
+                    vec![LinComb::from(v("~0")), LinComb::from(v("a_0"))],
+                ),
+                FlatStatement::Assertion(
+                    LinComb::from(v("~0")),
+                    LinComb::from(v("a_0")),
+                    LinComb::from(v("~2")),
+                ),
+                FlatStatement::Directive(
+                    vec![v("~3")],
+                    Helper::Product,
+                    vec![LinComb::from(v("~1")), LinComb::from(v("a_1"))],
+                ),
+                FlatStatement::Assertion(
+                    LinComb::from(v("~1")),
+                    LinComb::from(v("a_1")),
+                    LinComb::from(v("~3")),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn flatten_variable_select_composite() {
+        // {foo: field, bar: field[2]}[3] a, field i
+        // a[i]
+        let e = Structure::Select(
+            box Array::Identifier(Variable::array(
+                "a",
+                Type::Struct(vec![
+                    ("foo".to_string(), Type::FieldElement),
+                    ("bar".to_string(), Type::Array(box Type::FieldElement, 2)),
+                ]),
+                3,
+            )),
+            box FieldElement::Identifier(Variable::field_element("i")),
+        );
+
+        let mut flattener: Flattener<Bn128Field> = Flattener::new();
+
+        let flattened = e.flatten(&mut flattener);
+
+        // each of the 3 slots of the element is a sum of 3 products, one per element
+        assert_eq!(flattened.len(), 3);
+        assert!(flattened.iter().all(|e| e.0.len() == 3));
+
+        // the product for slot j of element k uses slot j of element k
+        let products: Vec<_> = flattener
+            .statements()
+            .iter()
+            .filter_map(|s| match s {
+                FlatStatement::Directive(_, Helper::Product, inputs) => Some(inputs[1].clone()),
+                _ => None,
+            }).collect();
+
+        assert_eq!(
+            products,
+            vec![0, 3, 6, 1, 4, 7, 2, 5, 8]
+                .into_iter()
+                .map(|i| LinComb::from(FlatVariable::with_name(format!("a_{}", i))))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn flatten_linear_arithmetic() {
+        // 2 * (a + 3 + a) + 4 * 5
+        let a = || box FieldElement::Identifier(Variable::field_element("a"));
+        let e = FieldElement::Add(
+            box FieldElement::Mult(
+                box FieldElement::Value(Bn128Field::from(2)),
+                box FieldElement::Add(
+                    box FieldElement::Add(a(), box FieldElement::Value(Bn128Field::from(3))),
+                    a(),
+                ),
+            ),
+            box FieldElement::Mult(
+                box FieldElement::Value(Bn128Field::from(4)),
+                box FieldElement::Value(Bn128Field::from(5)),
+            ),
+        );
+
+        let mut flattener = Flattener::new();
+
+        assert_eq!(
+            e.flatten(&mut flattener),
+            vec![LinComb(vec![
+                (Bn128Field::from(4), FlatVariable::with_name("a_0")),
+                (Bn128Field::from(26), FlatVariable::one()),
+            ])]
+        );
+        assert_eq!(flattener.statements(), &vec![]);
+    }
+
+    #[test]
+    fn flatten_mult_sub_div() {
+        // (a * b - c) / d
+        let id = |name| box FieldElement::Identifier(Variable::field_element(name));
+        let e = FieldElement::Div(
+            box FieldElement::Sub(box FieldElement::Mult(id("a"), id("b")), id("c")),
+            id("d"),
+        );
+
+        let mut flattener = Flattener::new();
+
+        let flattened = e.flatten(&mut flattener);
+
+        let v = |name| FlatVariable::with_name(name);
+
+        // the difference is linear, so it takes no variable of its own
+        let difference = LinComb(vec![
+            (Bn128Field::from(1), v("~0")),
+            (-Bn128Field::from(1), v("c_0")),
+        ]);
+
+        assert_eq!(flattened, vec![LinComb::from(v("~2"))]);
+        assert_eq!(
+            flattener.into_statements(),
+            vec![
+                FlatStatement::Directive(
+                    vec![v("~0")],
+                    Helper::Product,
+                    vec![LinComb::from(v("a_0")), LinComb::from(v("b_0"))],
+                ),
+                FlatStatement::Assertion(
+                    LinComb::from(v("a_0")),
+                    LinComb::from(v("b_0")),
+                    LinComb::from(v("~0")),
+                ),
+                FlatStatement::Directive(
+                    vec![v("~1")],
+                    Helper::Inverse,
+                    vec![LinComb::from(v("d_0"))],
+                ),
+                FlatStatement::Assertion(
+                    LinComb::from(v("d_0")),
+                    LinComb::from(v("~1")),
+                    LinComb::constant(Bn128Field::from(1)),
+                ),
+                FlatStatement::Directive(
+                    vec![v("~2")],
+                    Helper::Product,
+                    vec![difference.clone(), LinComb::from(v("~1"))],
+                ),
+                FlatStatement::Assertion(
+                    difference,
+                    LinComb::from(v("~1")),
+                    LinComb::from(v("~2")),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn flatten_pow() {
+        // a**5 == a * (a**2)**2
+        let e = FieldElement::Pow(box FieldElement::Identifier(Variable::field_element("a")), 5);
+
+        let mut flattener = Flattener::new();
+
+        assert_eq!(
+            e.flatten(&mut flattener),
+            vec![LinComb::from(FlatVariable::with_name("~2"))]
+        );
+
+        let products: Vec<_> = flattener
+            .statements()
+            .iter()
+            .filter_map(|s| match s {
+                FlatStatement::Assertion(a, b, c) => Some(format!("{} * {} == {}", a, b, c)),
+                _ => None,
+            }).collect();
+
+        assert_eq!(
+            products,
+            vec![
+                "1 * a_0 * 1 * a_0 == 1 * ~0",
+                "1 * ~0 * 1 * ~0 == 1 * ~1",
+                "1 * a_0 * 1 * ~1 == 1 * ~2",
+            ]
+        );
+
+        // a**0 == 1
+        assert_eq!(
+            FieldElement::Pow(box FieldElement::Value(Bn128Field::from(42)), 0)
+                .flatten(&mut flattener),
+            vec![LinComb::constant(Bn128Field::from(1))]
+        );
+    }
+
+    #[test]
+    fn flatten_boolean_operators() {
+        let a = || box Boolean::Identifier(Variable::boolean("a"));
+        let b = || box Boolean::Identifier(Variable::boolean("b"));
+
+        let assertions = |e: Boolean<Bn128Field>| {
+            let mut flattener = Flattener::new();
+            let flattened = e.flatten(&mut flattener).pop().unwrap().to_string();
+            let assertions: Vec<_> = flattener
+                .statements()
+                .iter()
+                .filter_map(|s| match s {
+                    FlatStatement::Assertion(a, b, c) => {
+                        Some(format!("({}) * ({}) == ({})", a, b, c))
+                    }
+                    _ => None,
+                }).collect();
+            (flattened, assertions)
+        };
+        let product = || "(1 * a_0) * (1 * b_0) == (1 * ~0)".to_string();
+
+        // only the products take a constraint, the rest is linear
+        assert_eq!(
+            assertions(Boolean::And(a(), b())),
+            ("1 * ~0".to_string(), vec![product()])
+        );
+        assert_eq!(
+            assertions(Boolean::Or(a(), b())),
+            ("1 * a_0 + 1 * b_0 + -1 * ~0".to_string(), vec![product()])
+        );
+        assert_eq!(
+            assertions(Boolean::Not(a())),
+            ("1 * ~one + -1 * a_0".to_string(), vec![])
+        );
+        assert_eq!(
+            assertions(Boolean::Xor(a(), b())),
+            ("1 * a_0 + 1 * b_0 + -2 * ~0".to_string(), vec![product()])
+        );
+        assert_eq!(
+            assertions(Boolean::Eq(a(), b())),
+            (
+                "1 * ~one + 2 * ~0 + -1 * a_0 + -1 * b_0".to_string(),
+                vec![product()]
+            )
+        );
+    }
+
+    #[test]
+    fn flatten_constant_boolean_operators() {
+        // !(true ^ false) v false
+        let e = Boolean::Or(
+            box Boolean::Not(box Boolean::And(
+                box Boolean::Value(true),
+                box Boolean::Value(false),
+            )),
+            box Boolean::Value(false),
+        );
+
+        let mut flattener = Flattener::new();
+
+        assert_eq!(e.flatten(&mut flattener), vec![LinComb::constant(Bn128Field::from(1))]);
+        assert_eq!(flattener.statements(), &vec![]);
+    }
+
+    #[test]
+    fn flatten_constant_uint_operators() {
+        let c = |v| box Uint::Value(v, 8);
+        let fold = |e: Uint<Bn128Field>| {
+            let mut flattener = Flattener::new();
+            let flattened = e.flatten(&mut flattener).pop().unwrap();
+            assert_eq!(flattener.statements(), &vec![]);
+            flattened.as_constant().unwrap()
+        };
+
+        // arithmetic wraps around
+        assert_eq!(fold(Uint::Add(c(200), c(100))), Bn128Field::from(44));
+        assert_eq!(fold(Uint::Sub(c(1), c(2))), Bn128Field::from(255));
+        assert_eq!(fold(Uint::Mult(c(16), c(17))), Bn128Field::from(16));
+        assert_eq!(fold(Uint::And(c(0b1100), c(0b1010))), Bn128Field::from(0b1000));
+        assert_eq!(fold(Uint::Or(c(0b1100), c(0b1010))), Bn128Field::from(0b1110));
+        assert_eq!(fold(Uint::Xor(c(0b1100), c(0b1010))), Bn128Field::from(0b0110));
+        assert_eq!(fold(Uint::Not(c(0b1100))), Bn128Field::from(0b1111_0011));
+        assert_eq!(fold(Uint::LeftShift(c(0b1100_0011), 2)), Bn128Field::from(0b0000_1100));
+        assert_eq!(fold(Uint::RightShift(c(0b1100_0011), 2)), Bn128Field::from(0b0011_0000));
+        assert_eq!(fold(Uint::LeftShift(c(0b1100_0011), 8)), Bn128Field::from(0));
+    }
+
+    #[test]
+    fn flatten_uint_add() {
+        // a + b, with a and b of type u8
+        let id = |name| box Uint::Identifier(Variable::new(name, Type::Uint(8)));
+        let e: Uint<Bn128Field> = Uint::Add(id("a"), id("b"));
+
+        let mut flattener = Flattener::new();
+
+        // the sum is decomposed into 9 bits, and the low 8 are packed back
+        assert_eq!(
+            e.flatten(&mut flattener).pop().unwrap().to_string(),
+            (0..8)
+                .map(|i| format!("{} * ~{}", 1 << i, i))
+                .collect::<Vec<_>>()
+                .join(" + ")
+        );
+        assert_eq!(
+            flattener.statements()[0].to_string(),
+            "# ~0, ~1, ~2, ~3, ~4, ~5, ~6, ~7, ~8 := Bits(1 * a_0 + 1 * b_0)"
+        );
+        assert_eq!(flattener.statements().len(), 1 + 9 + 1);
+    }
+
+    #[test]
+    fn flatten_field_eq() {
+        // a == 42
+        let e = Boolean::FieldEq(
+            box FieldElement::Identifier(Variable::field_element("a")),
+            box FieldElement::Value(Bn128Field::from(42)),
+        );
+        let mut flattener = Flattener::new();
+
+        assert_eq!(
+            e.flatten(&mut flattener).pop().unwrap().to_string(),
+            "1 * ~one + -1 * ~1"
+        );
+
+        let statements: Vec<_> = flattener.statements().iter().map(|s| s.to_string()).collect();
+
+        assert_eq!(
+            statements,
+            vec![
+                "# ~0 := Inverse(1 * a_0 + -42 * ~one)",
+                "# ~1 := Product(1 * a_0 + -42 * ~one, 1 * ~0)",
+                "(1 * a_0 + -42 * ~one) * (1 * ~0) == (1 * ~1)",
+                "(1 * a_0 + -42 * ~one) * (1 * ~one + -1 * ~1) == (0 * ~one)",
+            ]
+        );
+    }
+
+    #[test]
+    fn flatten_structural_eq() {
+        // a == [1, 2]
+        let a = || {
+            Array::<Bn128Field, FieldElement<Bn128Field>>::Identifier(Variable::array(
+                "a",
+                Type::FieldElement,
+                2,
+            ))
+        };
+        let b = || {
+            Array::value(vec![
+                FieldElement::Value(Bn128Field::from(1)),
+                FieldElement::Value(Bn128Field::from(2)),
+            ]).unwrap()
+        };
+        let e = Boolean::StructuralEq(box a(), box b());
+        let mut flattener = Flattener::new();
+
+        assert_eq!(e.to_string(), "a == [1, 2]");
+        assert_eq!(e.flatten(&mut flattener).pop().unwrap().to_string(), "1 * ~one + -1 * ~5");
+        let statements: Vec<_> = flattener.statements().iter().map(|s| s.to_string()).collect();
+
+        // one comparison per slot, then one of their sum with the number of slots
+        assert_eq!(statements.len(), 4 + 4 + 4);
+        assert_eq!(
+            &statements[8..],
+            &[
+                "# ~4 := Inverse(0 * ~one + -1 * ~1 + -1 * ~3)",
+                "# ~5 := Product(0 * ~one + -1 * ~1 + -1 * ~3, 1 * ~4)",
+                "(0 * ~one + -1 * ~1 + -1 * ~3) * (1 * ~4) == (1 * ~5)",
+                "(0 * ~one + -1 * ~1 + -1 * ~3) * (1 * ~one + -1 * ~5) == (0 * ~one)",
+            ]
+        );
+
+        // assert_eq(a, [1, 2])
+        let s = Statement::AssertEq(box a(), box b());
+        let mut flattener = Flattener::new();
+        assert_eq!(s.flatten(&mut flattener), None);
+        let statements: Vec<_> = flattener.statements().iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            statements,
+            vec![
+                "(1 * a_0) * (1 * ~one) == (1 * ~one)",
+                "(1 * a_1) * (1 * ~one) == (2 * ~one)",
+            ]
+        );
+    }
+
+    #[test]
+    fn flatten_lt() {
+        // a < b
+        let e = Boolean::Lt(
+            box FieldElement::Identifier(Variable::field_element("a")),
+            box FieldElement::Identifier(Variable::field_element("b")),
+        );
+
+        let mut flattener: Flattener<Bn128Field> = Flattener::new();
+        e.flatten(&mut flattener);
+
+        // both operands are range checked, then the shifted difference is decomposed
+        let width = Bn128Field::get_required_bits() - 2;
+        let decompositions: Vec<_> = flattener
+            .statements()
+            .iter()
+            .filter_map(|s| match s {
+                FlatStatement::Directive(outputs, Helper::Bits, inputs) => {
+                    Some((inputs[0].to_string(), outputs.len()))
+                }
+                _ => None,
+            }).collect();
+
+        assert_eq!(
+            decompositions,
+            vec![
+                ("1 * a_0".to_string(), width),
+                ("1 * b_0".to_string(), width),
+                (
+                    format!("{} * ~one + 1 * a_0 + -1 * b_0", Bn128Field::from(2).pow(width)),
+                    width + 1
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn flatten_constant_comparisons() {
+        let compare = |e: Boolean<Bn128Field>| {
+            let mut flattener = Flattener::new();
+            let flattened = e.flatten(&mut flattener);
+            assert_eq!(flattener.statements(), &vec![]);
+            flattened
+        };
+        let c = |v| box FieldElement::Value(Bn128Field::from(v));
+        let bit = |v| vec![LinComb::constant(Bn128Field::from(v))];
+
+        assert_eq!(compare(Boolean::Lt(c(2), c(3))), bit(1));
+        assert_eq!(compare(Boolean::Lt(c(3), c(3))), bit(0));
+        assert_eq!(compare(Boolean::Le(c(3), c(3))), bit(1));
+        assert_eq!(compare(Boolean::Gt(c(3), c(2))), bit(1));
+        assert_eq!(compare(Boolean::Ge(c(2), c(3))), bit(0));
+        assert_eq!(compare(Boolean::FieldEq(c(2), c(2))), bit(1));
+        assert_eq!(compare(Boolean::FieldNeq(c(2), c(2))), bit(0));
+    }
+
+    #[test]
+    fn flatten_structural_eq_with_enum() {
+        // enum Op { Add(field), Swap(field[2]) }
+        let ty = Type::Struct(vec![(
+            "op".to_string(),
+            Type::Enum(
+                "Op".to_string(),
+                vec![
+                    ("Add".to_string(), Type::FieldElement),
+                    ("Swap".to_string(), Type::Array(box Type::FieldElement, 2)),
+                ],
+            ),
+        )]);
+        let s = |id| Structure::Identifier(Variable::new(id, ty.clone()));
+
+        // def main({op: Op} a, {op: Op} b) -> (bool) { return a == b }
+        let f: Function<Bn128Field> = Function::new(
+            "main",
+            vec![
+                Parameter::public(Variable::new("a", ty.clone())),
+                Parameter::public(Variable::new("b", ty.clone())),
+            ],
+            vec![Type::Boolean],
+            vec![Statement::Return(vec![box Boolean::StructuralEq(
+                box s("a"),
+                box s("b"),
+            )])],
+        );
+
+        let flattened = f.flatten().to_string();
+
+        // the slot only Swap uses is zero on both sides unless the tag is that of Swap, so that
+        // comparing it does not depend on the witness
+        assert!(flattened.contains("(0 * ~one + 1 * ~3) * (1 * a_2) == (0 * ~one)"));
+        assert!(flattened.contains("(0 * ~one + 1 * ~7) * (1 * b_2) == (0 * ~one)"));
+        assert!(flattened.contains("# ~12 := Inverse(1 * a_2 + -1 * b_2)"));
+    }
+
+    #[test]
+    fn flatten_large_constant_comparison() {
+        // 0 - 1 < a, which would wrap around when shifted
+        let e = Boolean::Lt(
+            box FieldElement::Sub(
+                box FieldElement::Value(Bn128Field::from(0)),
+                box FieldElement::Value(Bn128Field::from(1)),
+            ),
+            box FieldElement::Identifier(Variable::field_element("a")),
+        );
+        let mut flattener = Flattener::new();
+
+        e.flatten(&mut flattener);
+        assert_eq!(
+            flattener.errors(),
+            &vec!["cannot compare -1, which does not fit in 252 bits".to_string()]
+        );
+    }
+
+    #[test]
+    fn flatten_if_else() {
+        // if c then s else t, with s and t of type {a: field[2], b: bool}
+        let ty = vec![
+            ("a".to_string(), Type::Array(box Type::FieldElement, 2)),
+            ("b".to_string(), Type::Boolean),
+        ];
+        let s = || box Structure::Identifier(Variable::structure("s", ty.clone()));
+        let t = || box Structure::Identifier(Variable::structure("t", ty.clone()));
+        let v = |name| FlatVariable::with_name(name);
+
+        let e = Structure::IfElse(box Boolean::Identifier(Variable::boolean("c")), s(), t());
+
+        let mut flattener: Flattener<Bn128Field> = Flattener::new();
+
+        // c * (s_i - t_i) + t_i for each slot
+        assert_eq!(
+            e.flatten(&mut flattener),
+            vec![
+                LinComb::from(v("~0")) + LinComb::from(v("t_0")),
+                LinComb::from(v("~1")) + LinComb::from(v("t_1")),
+                LinComb::from(v("~2")) + LinComb::from(v("t_2")),
+            ]
+        );
+        let difference = LinComb::from(v("s_0")) - LinComb::from(v("t_0"));
+        assert_eq!(
+            flattener.statements()[..2],
+            [
+                FlatStatement::Directive(
+                    vec![v("~0")],
+                    Helper::Product,
+                    vec![LinComb::from(v("c_0")), difference.clone()],
+                ),
+                FlatStatement::Assertion(
+                    LinComb::from(v("c_0")),
+                    difference,
+                    LinComb::from(v("~0")),
+                ),
+            ]
+        );
+
+        // a constant condition picks a branch
+        let e = Structure::IfElse(box Boolean::Value(false), s(), t());
+
+        let mut flattener = Flattener::new();
+
+        assert_eq!(
+            e.flatten(&mut flattener),
+            vec![
+                LinComb::from(v("t_0")),
+                LinComb::from(v("t_1")),
+                LinComb::from(v("t_2")),
+            ]
+        );
+        assert_eq!(flattener.statements(), &vec![]);
+    }
+
+    #[test]
+    fn flatten_function_call() {
+        // def square(field x) { return x * x }
+        // def main() { field a = square(square(2)) }
+        let functions = vec![Function::new(
+            "square",
+            vec![Parameter::public(Variable::field_element("x"))],
+            vec![Type::FieldElement],
+            vec![Statement::Return(vec![box FieldElement::Mult(
+                box FieldElement::Identifier(Variable::field_element("x")),
+                box FieldElement::Identifier(Variable::field_element("x")),
+            )])],
+        )];
+        let main = Function::with_statements(vec![Statement::Definition(
+            Variable::field_element("a"),
+            box FieldElement::FunctionCall(
+                "square".to_string(),
+                vec![box FieldElement::FunctionCall(
+                    "square".to_string(),
+                    vec![box FieldElement::Value(Bn128Field::from(2))],
+                )],
+            ),
+        )]);
+
+        let flattened = main.flatten_with(&functions);
+
+        // each call binds its own copy of the parameters, the inner one being flattened first
+        assert_eq!(
+            flattened.to_string(),
+            vec![
+                "# square~0.x_0 := Identity(2 * ~one)",
+                "(1 * square~0.x_0) * (1 * ~one) == (2 * ~one)",
+                "# ~0 := Product(1 * square~0.x_0, 1 * square~0.x_0)",
+                "(1 * square~0.x_0) * (1 * square~0.x_0) == (1 * ~0)",
+                "# square~1.x_0 := Identity(1 * ~0)",
+                "(1 * square~1.x_0) * (1 * ~one) == (1 * ~0)",
+                "# ~1 := Product(1 * square~1.x_0, 1 * square~1.x_0)",
+                "(1 * square~1.x_0) * (1 * square~1.x_0) == (1 * ~1)",
+                "# a_0 := Identity(1 * ~1)",
+                "(1 * a_0) * (1 * ~one) == (1 * ~1)",
+            ].join("\n")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot call square taking 1 parameters with 2 arguments")]
+    fn call_wrong_arity() {
+        // def square(field x) { return x * x }
+        // def main() { field a = square(1, 2) }
+        let functions = vec![Function::new(
+            "square",
+            vec![Parameter::public(Variable::field_element("x"))],
+            vec![Type::FieldElement],
+            vec![Statement::Return(vec![box FieldElement::Mult(
+                box FieldElement::Identifier(Variable::field_element("x")),
+                box FieldElement::Identifier(Variable::field_element("x")),
+            )])],
+        )];
+        let main = Function::with_statements(vec![Statement::Definition(
+            Variable::field_element("a"),
+            box FieldElement::FunctionCall(
+                "square".to_string(),
+                vec![
+                    box FieldElement::Value(Bn128Field::from(1)),
+                    box FieldElement::Value(Bn128Field::from(2)),
+                ],
+            ),
+        )]);
+
+        main.flatten_with(&functions);
+    }
+
+    #[test]
+    fn flatten_composite_return() {
+        // return [1, 2], true
+        let f = Function::with_statements(vec![Statement::Return(vec![
+            box Array::Value(vec![
+                FieldElement::Value(Bn128Field::from(1)),
+                FieldElement::Value(Bn128Field::from(2)),
+            ]),
+            box Boolean::Value(true),
+        ])]);
+
+        let flattened = f.flatten();
+
+        assert_eq!(
+            flattened.to_string(),
+            vec![
+                "# ~out_0, ~out_1, ~out_2 := Identity(1 * ~one, 2 * ~one, 1 * ~one)",
+                "(1 * ~out_0) * (1 * ~one) == (1 * ~one)",
+                "(1 * ~out_1) * (1 * ~one) == (2 * ~one)",
+                "(1 * ~out_2) * (1 * ~one) == (1 * ~one)",
+                "return ~out_0, ~out_1, ~out_2",
+            ].join("\n")
+        );
+    }
+
+    #[test]
+    fn flatten_for() {
+        // for field i in 1..3 {
+        //   field b = a[i] * i
+        // }
+        let a = || box Array::Identifier(Variable::array("a", Type::FieldElement, 3));
+        let i = || box FieldElement::Identifier(Variable::field_element("i"));
+        let f: Function<Bn128Field> = Function::with_statements(vec![Statement::For(
+            Variable::field_element("i"),
+            1,
+            3,
+            vec![Statement::Definition(
+                Variable::field_element("b"),
+                box FieldElement::Mult(box FieldElement::Select(a(), i()), i()),
+            )],
+        )]);
+
+        let flattened = f.flatten();
+
+        // `i` is a constant in each iteration, so the selection is a slice and the product is
+        // a scaling
+        assert_eq!(
+            flattened.to_string(),
+            vec![
+                "# b_0 := Identity(1 * a_1)",
+                "(1 * b_0) * (1 * ~one) == (1 * a_1)",
+                "# b~1_0 := Identity(2 * a_2)",
+                "(1 * b~1_0) * (1 * ~one) == (2 * a_2)",
+            ].join("\n")
+        );
+    }
+
+    #[test]
+    fn flatten_assignment() {
+        // field a = 1
+        // a = a + 1
+        // return a
+        let a = || Variable::field_element("a");
+        let f = Function::with_statements(vec![
+            Statement::Definition(a(), box FieldElement::Value(Bn128Field::from(1))),
+            Statement::Assignment(
+                a(),
+                box FieldElement::Add(
+                    box FieldElement::Identifier(a()),
+                    box FieldElement::Value(Bn128Field::from(1)),
+                ),
+            ),
+            Statement::Return(vec![box FieldElement::Identifier(a())]),
+        ]);
+
+        let flattened = f.flatten();
+
+        // the assignment reads the first version and defines the second one, which is returned
+        assert_eq!(
+            flattened.to_string(),
+            vec![
+                "# a_0 := Identity(1 * ~one)",
+                "(1 * a_0) * (1 * ~one) == (1 * ~one)",
+                "# a~1_0 := Identity(1 * a_0 + 1 * ~one)",
+                "(1 * a~1_0) * (1 * ~one) == (1 * a_0 + 1 * ~one)",
+                "# ~out_0 := Identity(1 * a~1_0)",
+                "(1 * ~out_0) * (1 * ~one) == (1 * a~1_0)",
+                "return ~out_0",
+            ].join("\n")
+        );
+    }
+
+    #[test]
+    fn flatten_scopes() {
+        // field a = 1
+        // field s = 0
+        // for field i in 1..3 {
+        //   field a = i
+        //   s = s + a
+        // }
+        // return a, s
+        let a = || Variable::field_element("a");
+        let s = || Variable::field_element("s");
+        let f = Function::with_statements(vec![
+            Statement::Definition(a(), box FieldElement::Value(Bn128Field::from(1))),
+            Statement::Definition(s(), box FieldElement::Value(Bn128Field::from(0))),
+            Statement::For(
+                Variable::field_element("i"),
+                1,
+                3,
+                vec![
+                    Statement::Definition(
+                        a(),
+                        box FieldElement::Identifier(Variable::field_element("i")),
+                    ),
+                    Statement::Assignment(
+                        s(),
+                        box FieldElement::Add(
+                            box FieldElement::Identifier(s()),
+                            box FieldElement::Identifier(a()),
+                        ),
+                    ),
+                ],
+            ),
+            Statement::Return(vec![
+                box FieldElement::Identifier(a()),
+                box FieldElement::Identifier(s()),
+            ]),
+        ]);
+
+        let flattened = f.flatten();
+
+        // the inner `a` gets its own versions and goes out of scope with the loop body, while
+        // the assignments to `s` outlive it
+        assert_eq!(
+            flattened
+                .to_string()
+                .lines()
+                .filter(|s| s.starts_with('#'))
+                .collect::<Vec<_>>(),
+            vec![
+                "# a_0 := Identity(1 * ~one)",
+                "# s_0 := Identity(0 * ~one)",
+                "# a~1_0 := Identity(1 * ~one)",
+                "# s~1_0 := Identity(1 * s_0 + 1 * a~1_0)",
+                "# a~2_0 := Identity(2 * ~one)",
+                "# s~2_0 := Identity(1 * s~1_0 + 1 * a~2_0)",
+                "# ~out_0, ~out_1 := Identity(1 * a_0, 1 * s~2_0)",
+            ]
+        );
+    }
 }
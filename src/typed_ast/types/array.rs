@@ -1,46 +1,161 @@
+use field::Field;
 use flat_ast::*;
+use num_traits::ToPrimitive;
 use std::fmt;
-use typed_ast::{Expression, Variable};
+use typed_ast::types::{
+    flatten_if_else, flatten_match, flatten_unwrap_or, fmt_match, Boolean, Enumeration,
+    FieldElement, MatchArm, Optional, Structure, Tuple,
+};
+use typed_ast::{Expression, Flattener, Type, TypeError, Variable};
 
 #[derive(Debug)]
-pub enum Array<V: Expression> {
+pub enum Array<T: Field, V: Expression<T>> {
+    // non-empty, all elements of the same type
     Value(Vec<V>),
     Identifier(Variable),
-    FunctionCall(String, Vec<Box<Expression>>),
+    Member(Box<Structure<T>>, String),
+    Element(Box<Tuple<T>>, usize),
+    // the array being indexed is an array of `Array<T, V>`, which cannot be named here without
+    // making the type infinitely recursive
+    Select(Box<Expression<T>>, Box<FieldElement<T>>),
+    IfElse(Box<Boolean<T>>, Box<Array<T, V>>, Box<Array<T, V>>),
+    Match(Box<Enumeration<T>>, Vec<MatchArm<Array<T, V>>>),
+    UnwrapOr(Box<Optional<T>>, Box<Array<T, V>>),
+    FunctionCall(Type, String, Vec<Box<Expression<T>>>),
 }
 
-impl<V: Expression> Array<V> {
-    pub fn value(elements: Vec<V>) -> Array<V> {
-        assert_eq!(2, elements.len());
-        Array::Value(elements)
+// an element is laid out on a contiguous run of the array's slots, starting at its index times
+// the element size. If the index is only known at witness time, every element is multiplied by
+// one bit of a one-hot selector and the products are summed up, slot by slot. A constant index
+// out of bounds is reported, and selects zeros
+pub fn flatten_select<T: Field>(
+    array: &Expression<T>,
+    index: &FieldElement<T>,
+    flattener: &mut Flattener<T>,
+) -> Vec<LinComb<T>> {
+    let (inner, size) = match array.get_type() {
+        Type::Array(inner, size) => (inner, size),
+        ty => panic!("cannot index into {} of type {}", array, ty),
+    };
+
+    let e = array.flatten(flattener);
+    let i = index.flatten(flattener).pop().unwrap();
+
+    match i.as_constant() {
+        Some(i) => match i.to_biguint().to_usize() {
+            Some(i) if i < size => e[i * inner.size()..(i + 1) * inner.size()].to_vec(),
+            _ => {
+                flattener.error(format!(
+                    "index {} out of bounds for {} of type {}",
+                    i,
+                    array,
+                    array.get_type()
+                ));
+                vec![LinComb::constant(T::zero()); inner.size()]
+            }
+        },
+        None => {
+            let selector: Vec<_> = (0..size).map(|_| flattener.use_sym()).collect();
+
+            flattener.push(FlatStatement::Directive(
+                selector.clone(),
+                Helper::Selector,
+                vec![i.clone()],
+            ));
+
+            for (k, s) in selector.iter().enumerate() {
+                flattener.assert_bit(LinComb::from(s.clone()));
+                // if s is set then the index is k
+                flattener.push(FlatStatement::Assertion(
+                    LinComb::from(s.clone()),
+                    i.clone(),
+                    LinComb(vec![(T::from(k), s.clone())]),
+                ));
+            }
+
+            // exactly one bit is set
+            flattener.push(FlatStatement::Assertion(
+                LinComb(selector.iter().map(|s| (T::one(), s.clone())).collect()),
+                LinComb::constant(T::one()),
+                LinComb::constant(T::one()),
+            ));
+
+            (0..inner.size())
+                .map(|j| {
+                    selector
+                        .iter()
+                        .enumerate()
+                        .map(|(k, s)| {
+                            flattener.mult(
+                                LinComb::from(s.clone()),
+                                e[k * inner.size() + j].clone(),
+                            )
+                        }).fold(LinComb(vec![]), |acc, p| acc + p)
+                }).collect()
+        }
     }
 }
 
-impl<V: Expression> Expression for Array<V> {
-    fn flatten(&self, flatten_statements: &mut Vec<FlatStatement>) -> Vec<LinComb> {
+impl<T: Field, V: Expression<T>> Array<T, V> {
+    pub fn value(elements: Vec<V>) -> Result<Array<T, V>, TypeError> {
+        let ty = match elements.first() {
+            Some(e) => e.get_type(),
+            None => return Err(TypeError::new("empty arrays are not supported")),
+        };
+
+        match elements.iter().find(|e| e.get_type() != ty) {
+            Some(e) => Err(TypeError::new(format!(
+                "expected an element of type {}, found {} of type {}",
+                ty,
+                e,
+                e.get_type()
+            ))),
+            None => Ok(Array::Value(elements)),
+        }
+    }
+}
+
+impl<T: Field, V: Expression<T>> Expression<T> for Array<T, V> {
+    fn flatten(&self, flattener: &mut Flattener<T>) -> Vec<LinComb<T>> {
         match *self {
-            Array::Identifier(ref v) => vec![0, 1]
-                .iter()
-                .map(|n| {
-                    LinComb(vec![(
-                        1,
-                        FlatVariable::with_name(format!("{}_{}", v.name(), n)),
-                    )])
-                }).collect(),
+            Array::Identifier(ref v) => flattener.identifier(v),
             Array::Value(ref v) => v
                 .iter()
-                .map(|v| v.flatten(flatten_statements))
+                .map(|v| v.flatten(flattener))
                 .flat_map(|x| x)
                 .collect(),
-            _ => unimplemented!(),
+            Array::Member(ref s, ref id) => s.flatten_member(id, flattener),
+            Array::Element(ref t, index) => t.flatten_element(index, flattener),
+            Array::Select(ref a, ref i) => flatten_select(&**a, i, flattener),
+            Array::IfElse(ref c, ref a, ref b) => flatten_if_else(c, &**a, &**b, flattener),
+            Array::Match(ref e, ref arms) => flatten_match(e, arms, flattener),
+            Array::UnwrapOr(ref o, ref d) => flatten_unwrap_or(o, &**d, flattener),
+            Array::FunctionCall(_, ref id, ref args) => flattener.call(id, args),
+        }
+    }
+
+    fn get_type(&self) -> Type {
+        match *self {
+            Array::Value(ref v) => Type::Array(box v[0].get_type(), v.len()),
+            Array::Identifier(ref v) => v.get_type(),
+            Array::Member(ref s, ref id) => s.member_type(id),
+            Array::Element(ref t, index) => t.element_type(index),
+            Array::Select(ref a, _) => match a.get_type() {
+                Type::Array(inner, _) => *inner,
+                ty => panic!("cannot index into {} of type {}", a, ty),
+            },
+            Array::IfElse(_, ref a, _) => a.get_type(),
+            Array::Match(_, ref arms) => arms[0].expression.get_type(),
+            Array::UnwrapOr(_, ref d) => d.get_type(),
+            Array::FunctionCall(ref ty, ..) => ty.clone(),
         }
     }
 }
 
-impl<V: Expression> fmt::Display for Array<V> {
+impl<T: Field, V: Expression<T>> fmt::Display for Array<T, V> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Array::Identifier(ref id) => write!(f, "{:?}", id),
+            Array::Identifier(ref id) => write!(f, "{}", id),
             Array::Value(ref values) => write!(
                 f,
                 "[{}]",
@@ -50,7 +165,13 @@ impl<V: Expression> fmt::Display for Array<V> {
                     .collect::<Vec<_>>()
                     .join(", ")
             ),
-            Array::FunctionCall(ref id, ref args) => write!(
+            Array::Member(ref s, ref id) => write!(f, "{}.{}", s, id),
+            Array::Element(ref t, index) => write!(f, "{}.{}", t, index),
+            Array::Select(ref a, ref i) => write!(f, "{}[{}]", a, i),
+            Array::IfElse(ref c, ref a, ref b) => write!(f, "if {} then {} else {}", c, a, b),
+            Array::Match(ref e, ref arms) => fmt_match(f, e, arms),
+            Array::UnwrapOr(ref o, ref d) => write!(f, "{}.unwrap_or({})", o, d),
+            Array::FunctionCall(_, ref id, ref args) => write!(
                 f,
                 "{}({})",
                 id,
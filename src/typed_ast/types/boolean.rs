@@ -1,36 +1,216 @@
+use field::Field;
 use flat_ast::*;
 use std::fmt;
-use typed_ast::{Expression, Variable};
+use typed_ast::types::{
+    flatten_match, flatten_select, flatten_unwrap_or, fmt_match, Array, Enumeration, FieldElement,
+    MatchArm, Optional, Structure, Tuple,
+};
+use typed_ast::{Expression, Flattener, Type, Variable};
 
 #[derive(Debug)]
-pub enum Boolean {
+pub enum Boolean<T: Field> {
     Identifier(Variable),
     Value(bool),
-    And(Box<Boolean>, Box<Boolean>),
-    FunctionCall(String, Vec<Box<Expression>>),
+    And(Box<Boolean<T>>, Box<Boolean<T>>),
+    Or(Box<Boolean<T>>, Box<Boolean<T>>),
+    Not(Box<Boolean<T>>),
+    Xor(Box<Boolean<T>>, Box<Boolean<T>>),
+    Eq(Box<Boolean<T>>, Box<Boolean<T>>),
+    Lt(Box<FieldElement<T>>, Box<FieldElement<T>>),
+    Le(Box<FieldElement<T>>, Box<FieldElement<T>>),
+    Gt(Box<FieldElement<T>>, Box<FieldElement<T>>),
+    Ge(Box<FieldElement<T>>, Box<FieldElement<T>>),
+    FieldEq(Box<FieldElement<T>>, Box<FieldElement<T>>),
+    FieldNeq(Box<FieldElement<T>>, Box<FieldElement<T>>),
+    // two arrays or two structures of the same type, compared slot by slot
+    StructuralEq(Box<Expression<T>>, Box<Expression<T>>),
+    StructuralNeq(Box<Expression<T>>, Box<Expression<T>>),
+    IsSome(Box<Optional<T>>),
+    Member(Box<Structure<T>>, String),
+    Element(Box<Tuple<T>>, usize),
+    Select(Box<Array<T, Boolean<T>>>, Box<FieldElement<T>>),
+    IfElse(Box<Boolean<T>>, Box<Boolean<T>>, Box<Boolean<T>>),
+    Match(Box<Enumeration<T>>, Vec<MatchArm<Boolean<T>>>),
+    UnwrapOr(Box<Optional<T>>, Box<Boolean<T>>),
+    FunctionCall(String, Vec<Box<Expression<T>>>),
 }
 
-impl Expression for Boolean {
-    fn flatten(&self, flatten_statements: &mut Vec<FlatStatement>) -> Vec<LinComb> {
+// `c * (a - b) + b`, slot by slot. A constant condition picks its branch without flattening the
+// other one
+pub fn flatten_if_else<T: Field>(
+    condition: &Boolean<T>,
+    consequence: &Expression<T>,
+    alternative: &Expression<T>,
+    flattener: &mut Flattener<T>,
+) -> Vec<LinComb<T>> {
+    let c = condition.flatten(flattener).pop().unwrap();
+
+    match c.as_constant() {
+        Some(ref c) if c.is_zero() => alternative.flatten(flattener),
+        Some(_) => consequence.flatten(flattener),
+        None => {
+            let a = consequence.flatten(flattener);
+            let b = alternative.flatten(flattener);
+            a.into_iter()
+                .zip(b)
+                .map(|(a, b)| {
+                    let difference = a - b.clone();
+                    flattener.mult(c.clone(), difference) + b
+                }).collect()
+        }
+    }
+}
+
+// `a == b` as a bit: the slots are compared one by one, and the values are equal if and only if
+// the bits add up to the number of slots. Equal values have equal slots, as the ones the variant
+// of an enum does not use are zeros
+pub fn flatten_structural_eq<T: Field>(
+    a: &Expression<T>,
+    b: &Expression<T>,
+    flattener: &mut Flattener<T>,
+) -> LinComb<T> {
+    let a = a.flatten(flattener);
+    let b = b.flatten(flattener);
+    let count = a.len();
+    let sum = a
+        .into_iter()
+        .zip(b)
+        .map(|(a, b)| flattener.eq(a, b))
+        .fold(LinComb(vec![]), |acc, eq| acc + eq);
+    flattener.eq(sum, LinComb::constant(T::from(count)))
+}
+
+impl<T: Field> Expression<T> for Boolean<T> {
+    fn flatten(&self, flattener: &mut Flattener<T>) -> Vec<LinComb<T>> {
         match *self {
             Boolean::Value(b) => {
                 if b {
-                    vec![LinComb(vec![(1, FlatVariable::one())])]
+                    vec![LinComb::constant(T::one())]
                 } else {
-                    vec![LinComb(vec![(0, FlatVariable::one())])]
+                    vec![LinComb::constant(T::zero())]
                 }
             }
-            _ => unimplemented!(),
+            Boolean::Identifier(ref v) => flattener.identifier(v),
+            Boolean::And(ref e1, ref e2) => {
+                let e1 = e1.flatten(flattener).pop().unwrap();
+                let e2 = e2.flatten(flattener).pop().unwrap();
+                vec![flattener.mult(e1, e2)]
+            }
+            Boolean::Or(ref e1, ref e2) => {
+                // a + b - a * b
+                let e1 = e1.flatten(flattener).pop().unwrap();
+                let e2 = e2.flatten(flattener).pop().unwrap();
+                let product = flattener.mult(e1.clone(), e2.clone());
+                vec![e1 + e2 - product]
+            }
+            Boolean::Not(ref e) => {
+                // 1 - a
+                let e = e.flatten(flattener).pop().unwrap();
+                vec![LinComb::constant(T::one()) - e]
+            }
+            Boolean::Xor(ref e1, ref e2) => {
+                // a + b - 2 * a * b
+                let e1 = e1.flatten(flattener).pop().unwrap();
+                let e2 = e2.flatten(flattener).pop().unwrap();
+                let product = flattener.mult(e1.clone(), e2.clone());
+                vec![e1 + e2 - product.scale(T::from(2))]
+            }
+            Boolean::Eq(ref e1, ref e2) => {
+                // 1 - (a xor b) == 1 + 2 * a * b - (a + b)
+                let e1 = e1.flatten(flattener).pop().unwrap();
+                let e2 = e2.flatten(flattener).pop().unwrap();
+                let product = flattener.mult(e1.clone(), e2.clone());
+                vec![LinComb::constant(T::one()) + product.scale(T::from(2)) - (e1 + e2)]
+            }
+            Boolean::Lt(ref e1, ref e2) => {
+                let e1 = e1.flatten(flattener).pop().unwrap();
+                let e2 = e2.flatten(flattener).pop().unwrap();
+                vec![flattener.lt(e1, e2)]
+            }
+            Boolean::Le(ref e1, ref e2) => {
+                // !(b < a)
+                let e1 = e1.flatten(flattener).pop().unwrap();
+                let e2 = e2.flatten(flattener).pop().unwrap();
+                let gt = flattener.lt(e2, e1);
+                vec![LinComb::constant(T::one()) - gt]
+            }
+            Boolean::Gt(ref e1, ref e2) => {
+                // b < a
+                let e1 = e1.flatten(flattener).pop().unwrap();
+                let e2 = e2.flatten(flattener).pop().unwrap();
+                vec![flattener.lt(e2, e1)]
+            }
+            Boolean::Ge(ref e1, ref e2) => {
+                // !(a < b)
+                let e1 = e1.flatten(flattener).pop().unwrap();
+                let e2 = e2.flatten(flattener).pop().unwrap();
+                let lt = flattener.lt(e1, e2);
+                vec![LinComb::constant(T::one()) - lt]
+            }
+            Boolean::FieldEq(ref e1, ref e2) => {
+                let e1 = e1.flatten(flattener).pop().unwrap();
+                let e2 = e2.flatten(flattener).pop().unwrap();
+                vec![flattener.eq(e1, e2)]
+            }
+            Boolean::FieldNeq(ref e1, ref e2) => {
+                let e1 = e1.flatten(flattener).pop().unwrap();
+                let e2 = e2.flatten(flattener).pop().unwrap();
+                let eq = flattener.eq(e1, e2);
+                vec![LinComb::constant(T::one()) - eq]
+            }
+            Boolean::StructuralEq(ref e1, ref e2) => {
+                vec![flatten_structural_eq(&**e1, &**e2, flattener)]
+            }
+            Boolean::StructuralNeq(ref e1, ref e2) => {
+                let eq = flatten_structural_eq(&**e1, &**e2, flattener);
+                vec![LinComb::constant(T::one()) - eq]
+            }
+            Boolean::IsSome(ref o) => {
+                // the presence flag
+                let mut e = o.flatten(flattener);
+                e.truncate(1);
+                e
+            }
+            Boolean::Member(ref s, ref id) => s.flatten_member(id, flattener),
+            Boolean::Element(ref t, index) => t.flatten_element(index, flattener),
+            Boolean::Select(ref a, ref i) => flatten_select(&**a, i, flattener),
+            Boolean::IfElse(ref c, ref a, ref b) => flatten_if_else(c, &**a, &**b, flattener),
+            Boolean::Match(ref e, ref arms) => flatten_match(e, arms, flattener),
+            Boolean::UnwrapOr(ref o, ref d) => flatten_unwrap_or(o, &**d, flattener),
+            Boolean::FunctionCall(ref id, ref args) => flattener.call(id, args),
         }
     }
+
+    fn get_type(&self) -> Type {
+        Type::Boolean
+    }
 }
 
-impl fmt::Display for Boolean {
+impl<T: Field> fmt::Display for Boolean<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Boolean::Identifier(ref id) => write!(f, "{:?}", id),
+            Boolean::Identifier(ref id) => write!(f, "{}", id),
             Boolean::Value(ref v) => write!(f, "{}", v),
             Boolean::And(ref b1, ref b2) => write!(f, "{} ^ {}", b1, b2),
+            Boolean::Or(ref b1, ref b2) => write!(f, "{} v {}", b1, b2),
+            Boolean::Not(ref b) => write!(f, "!{}", b),
+            Boolean::Xor(ref b1, ref b2) => write!(f, "{} xor {}", b1, b2),
+            Boolean::Eq(ref b1, ref b2) => write!(f, "{} == {}", b1, b2),
+            Boolean::Lt(ref f1, ref f2) => write!(f, "{} < {}", f1, f2),
+            Boolean::Le(ref f1, ref f2) => write!(f, "{} <= {}", f1, f2),
+            Boolean::Gt(ref f1, ref f2) => write!(f, "{} > {}", f1, f2),
+            Boolean::Ge(ref f1, ref f2) => write!(f, "{} >= {}", f1, f2),
+            Boolean::FieldEq(ref f1, ref f2) => write!(f, "{} == {}", f1, f2),
+            Boolean::FieldNeq(ref f1, ref f2) => write!(f, "{} != {}", f1, f2),
+            Boolean::StructuralEq(ref e1, ref e2) => write!(f, "{} == {}", e1, e2),
+            Boolean::StructuralNeq(ref e1, ref e2) => write!(f, "{} != {}", e1, e2),
+            Boolean::IsSome(ref o) => write!(f, "{}.is_some()", o),
+            Boolean::Member(ref s, ref id) => write!(f, "{}.{}", s, id),
+            Boolean::Element(ref t, index) => write!(f, "{}.{}", t, index),
+            Boolean::Select(ref a, ref i) => write!(f, "{}[{}]", a, i),
+            Boolean::IfElse(ref c, ref a, ref b) => write!(f, "if {} then {} else {}", c, a, b),
+            Boolean::Match(ref e, ref arms) => fmt_match(f, e, arms),
+            Boolean::UnwrapOr(ref o, ref d) => write!(f, "{}.unwrap_or({})", o, d),
             Boolean::FunctionCall(ref id, ref args) => write!(
                 f,
                 "{}({})",
@@ -44,7 +224,7 @@ impl fmt::Display for Boolean {
     }
 }
 
-impl Boolean {
+impl<T: Field> Boolean<T> {
     pub fn _true() -> Self {
         Boolean::Value(true)
     }
@@ -0,0 +1,226 @@
+use field::Field;
+use flat_ast::*;
+use std::fmt;
+use typed_ast::types::{
+    flatten_if_else, flatten_select, flatten_unwrap_or, Array, Boolean, FieldElement, Optional,
+    Structure, Tuple,
+};
+use typed_ast::{Expression, Flattener, Type, TypeError, Variable};
+
+// A value of one of the variants of an enum, laid out as its tag followed by its payload, padded
+// with zeros up to the size of the largest one
+#[derive(Debug)]
+pub enum Enumeration<T: Field> {
+    // the enum type, the variant and its payload
+    Variant(Type, String, Box<Expression<T>>),
+    Identifier(Variable),
+    Member(Box<Structure<T>>, String),
+    Element(Box<Tuple<T>>, usize),
+    Select(Box<Array<T, Enumeration<T>>>, Box<FieldElement<T>>),
+    IfElse(Box<Boolean<T>>, Box<Enumeration<T>>, Box<Enumeration<T>>),
+    Match(Box<Enumeration<T>>, Vec<MatchArm<Enumeration<T>>>),
+    UnwrapOr(Box<Optional<T>>, Box<Enumeration<T>>),
+    FunctionCall(Type, String, Vec<Box<Expression<T>>>),
+}
+
+// `variant(binding) => expression`, the binding standing for the payload in the expression
+#[derive(Debug)]
+pub struct MatchArm<E> {
+    pub variant: String,
+    pub binding: Variable,
+    pub expression: Box<E>,
+}
+
+impl<E: fmt::Display> fmt::Display for MatchArm<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}({}) => {}", self.variant, self.binding, self.expression)
+    }
+}
+
+// each arm is flattened under a gate on a bit which is set if and only if the tag is its own, so
+// that the assertions of the others, which read the payload of another variant, do not have to
+// hold. The results are summed up slot by slot after going through the same bits. Exactly one
+// bit must be set, which rules out tags no variant has. A constant tag picks its arm without
+// flattening the others
+pub fn flatten_match<T: Field, E: Expression<T>>(
+    e: &Enumeration<T>,
+    arms: &[MatchArm<E>],
+    flattener: &mut Flattener<T>,
+) -> Vec<LinComb<T>> {
+    let ty = e.get_type();
+    let mut slots = e.flatten(flattener);
+    let payload = slots.split_off(1);
+    let tag = slots.pop().unwrap();
+
+    let tags: Vec<_> = arms
+        .iter()
+        .map(|arm| match ty.variant(&arm.variant) {
+            Some((tag, _)) => tag,
+            None => panic!("{} has no variant {}", ty, arm.variant),
+        }).collect();
+
+    if let Some(tag) = tag.as_constant() {
+        let arm = match tags.iter().position(|t| T::from(*t) == tag) {
+            Some(index) => &arms[index],
+            None => panic!("no arm of the match on {} has tag {}", e, tag),
+        };
+        return flatten_arm(arm, payload, flattener);
+    }
+
+    let mut selectors = vec![];
+    let mut result: Option<Vec<LinComb<T>>> = None;
+    for (arm, t) in arms.iter().zip(tags) {
+        let selected = flattener.eq(tag.clone(), LinComb::constant(T::from(t)));
+        flattener.enter_gate(selected.clone());
+        let value = flatten_arm(arm, payload.clone(), flattener);
+        flattener.exit_gate();
+        let value: Vec<_> = value
+            .into_iter()
+            .map(|slot| flattener.mult(selected.clone(), slot))
+            .collect();
+        result = Some(match result {
+            Some(result) => result.into_iter().zip(value).map(|(a, b)| a + b).collect(),
+            None => value,
+        });
+        selectors.push(selected);
+    }
+
+    let sum = selectors
+        .into_iter()
+        .fold(LinComb(vec![]), |acc, selected| acc + selected);
+    flattener.push(FlatStatement::Assertion(
+        sum,
+        LinComb::constant(T::one()),
+        LinComb::constant(T::one()),
+    ));
+
+    result.unwrap_or_default()
+}
+
+// the expression of `arm`, in a block where its binding stands for the start of `payload`. The
+// slots other variants share with different types are only checked here
+fn flatten_arm<T: Field, E: Expression<T>>(
+    arm: &MatchArm<E>,
+    mut payload: Vec<LinComb<T>>,
+    flattener: &mut Flattener<T>,
+) -> Vec<LinComb<T>> {
+    payload.truncate(arm.binding.get_type().size());
+    flattener.assert_bits(&payload, &arm.binding.get_type());
+
+    flattener.enter_block();
+    flattener.substitute(&arm.binding, payload);
+    let e = arm.expression.flatten(flattener);
+    flattener.exit_block();
+    e
+}
+
+impl<T: Field> Enumeration<T> {
+    pub fn variant(
+        ty: Type,
+        id: String,
+        payload: Box<Expression<T>>,
+    ) -> Result<Enumeration<T>, TypeError> {
+        let expected = match ty.variant(&id) {
+            Some((_, expected)) => expected.clone(),
+            None => return Err(TypeError::new(format!("{} has no variant {}", ty, id))),
+        };
+        if payload.get_type() != expected {
+            return Err(TypeError::new(format!(
+                "expected {}::{} to hold a value of type {}, found {} of type {}",
+                ty,
+                id,
+                expected,
+                payload,
+                payload.get_type()
+            )));
+        }
+        Ok(Enumeration::Variant(ty, id, payload))
+    }
+}
+
+impl<T: Field> Expression<T> for Enumeration<T> {
+    fn flatten(&self, flattener: &mut Flattener<T>) -> Vec<LinComb<T>> {
+        match *self {
+            Enumeration::Variant(ref ty, ref id, ref payload) => {
+                let tag = match ty.variant(id) {
+                    Some((tag, _)) => tag,
+                    None => panic!("{} has no variant {}", ty, id),
+                };
+                let mut e = vec![LinComb::constant(T::from(tag))];
+                e.extend(payload.flatten(flattener));
+                e.resize(ty.size(), LinComb::constant(T::zero()));
+                e
+            }
+            Enumeration::Identifier(ref v) => flattener.identifier(v),
+            Enumeration::Member(ref s, ref id) => s.flatten_member(id, flattener),
+            Enumeration::Element(ref t, index) => t.flatten_element(index, flattener),
+            Enumeration::Select(ref a, ref i) => flatten_select(&**a, i, flattener),
+            Enumeration::IfElse(ref c, ref a, ref b) => flatten_if_else(c, &**a, &**b, flattener),
+            Enumeration::Match(ref e, ref arms) => flatten_match(e, arms, flattener),
+            Enumeration::UnwrapOr(ref o, ref d) => flatten_unwrap_or(o, &**d, flattener),
+            Enumeration::FunctionCall(_, ref id, ref args) => flattener.call(id, args),
+        }
+    }
+
+    fn get_type(&self) -> Type {
+        match *self {
+            Enumeration::Variant(ref ty, ..) => ty.clone(),
+            Enumeration::Identifier(ref v) => v.get_type(),
+            Enumeration::Member(ref s, ref id) => s.member_type(id),
+            Enumeration::Element(ref t, index) => t.element_type(index),
+            Enumeration::Select(ref a, _) => match a.get_type() {
+                Type::Array(inner, _) => *inner,
+                ty => panic!("cannot index into {} of type {}", a, ty),
+            },
+            Enumeration::IfElse(_, ref a, _) => a.get_type(),
+            Enumeration::Match(_, ref arms) => arms[0].expression.get_type(),
+            Enumeration::UnwrapOr(_, ref d) => d.get_type(),
+            Enumeration::FunctionCall(ref ty, ..) => ty.clone(),
+        }
+    }
+}
+
+// `match e { A(a) => x, B(b) => y }`
+pub fn fmt_match<E: fmt::Display>(
+    f: &mut fmt::Formatter,
+    e: &fmt::Display,
+    arms: &[MatchArm<E>],
+) -> fmt::Result {
+    write!(
+        f,
+        "match {} {{ {} }}",
+        e,
+        arms.iter()
+            .map(|arm| format!("{}", arm))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+impl<T: Field> fmt::Display for Enumeration<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Enumeration::Variant(ref ty, ref id, ref payload) => {
+                write!(f, "{}::{}({})", ty, id, payload)
+            }
+            Enumeration::Identifier(ref id) => write!(f, "{}", id),
+            Enumeration::Member(ref s, ref id) => write!(f, "{}.{}", s, id),
+            Enumeration::Element(ref t, index) => write!(f, "{}.{}", t, index),
+            Enumeration::Select(ref a, ref i) => write!(f, "{}[{}]", a, i),
+            Enumeration::IfElse(ref c, ref a, ref b) => {
+                write!(f, "if {} then {} else {}", c, a, b)
+            }
+            Enumeration::Match(ref e, ref arms) => fmt_match(f, e, arms),
+            Enumeration::UnwrapOr(ref o, ref d) => write!(f, "{}.unwrap_or({})", o, d),
+            Enumeration::FunctionCall(_, ref id, ref args) => write!(
+                f,
+                "{}({})",
+                id,
+                args.iter()
+                    .map(|e| format!("{}", e))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
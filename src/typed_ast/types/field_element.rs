@@ -1,35 +1,108 @@
+use field::Field;
 use flat_ast::*;
 use std::fmt;
-use typed_ast::{Expression, Variable};
+use typed_ast::types::{
+    flatten_if_else, flatten_match, flatten_select, flatten_unwrap_or, fmt_match, Array, Boolean,
+    Enumeration, MatchArm, Optional, Structure, Tuple,
+};
+use typed_ast::{Expression, Flattener, Type, Variable};
 
 #[derive(Debug)]
-pub enum FieldElement {
+pub enum FieldElement<T: Field> {
     Identifier(Variable),
-    Value(usize),
-    Add(Box<FieldElement>, Box<FieldElement>),
-    FunctionCall(String, Vec<Box<Expression>>),
+    Value(T),
+    Add(Box<FieldElement<T>>, Box<FieldElement<T>>),
+    Sub(Box<FieldElement<T>>, Box<FieldElement<T>>),
+    Mult(Box<FieldElement<T>>, Box<FieldElement<T>>),
+    Div(Box<FieldElement<T>>, Box<FieldElement<T>>),
+    Pow(Box<FieldElement<T>>, usize),
+    Member(Box<Structure<T>>, String),
+    Element(Box<Tuple<T>>, usize),
+    Select(Box<Array<T, FieldElement<T>>>, Box<FieldElement<T>>),
+    IfElse(Box<Boolean<T>>, Box<FieldElement<T>>, Box<FieldElement<T>>),
+    Match(Box<Enumeration<T>>, Vec<MatchArm<FieldElement<T>>>),
+    UnwrapOr(Box<Optional<T>>, Box<FieldElement<T>>),
+    FunctionCall(String, Vec<Box<Expression<T>>>),
 }
 
 // implement flattening for each type
-impl Expression for FieldElement {
-    fn flatten(&self, flatten_statements: &mut Vec<FlatStatement>) -> Vec<LinComb> {
+impl<T: Field> Expression<T> for FieldElement<T> {
+    fn flatten(&self, flattener: &mut Flattener<T>) -> Vec<LinComb<T>> {
         match *self {
-            FieldElement::Identifier(ref v) => vec![LinComb(vec![(
-                1,
-                FlatVariable::with_name(format!("{}_0", v.name())),
-            )])],
-            FieldElement::Value(ref v) => vec![LinComb(vec![(*v, FlatVariable::one())])],
-            _ => unimplemented!(),
+            FieldElement::Identifier(ref v) => flattener.identifier(v),
+            FieldElement::Value(ref v) => vec![LinComb::constant(v.clone())],
+            FieldElement::Add(ref e1, ref e2) => {
+                let e1 = e1.flatten(flattener).pop().unwrap();
+                let e2 = e2.flatten(flattener).pop().unwrap();
+                vec![e1 + e2]
+            }
+            FieldElement::Sub(ref e1, ref e2) => {
+                let e1 = e1.flatten(flattener).pop().unwrap();
+                let e2 = e2.flatten(flattener).pop().unwrap();
+                vec![e1 - e2]
+            }
+            FieldElement::Mult(ref e1, ref e2) => {
+                let e1 = e1.flatten(flattener).pop().unwrap();
+                let e2 = e2.flatten(flattener).pop().unwrap();
+                vec![flattener.mult(e1, e2)]
+            }
+            FieldElement::Div(ref e1, ref e2) => {
+                let e1 = e1.flatten(flattener).pop().unwrap();
+                let e2 = e2.flatten(flattener).pop().unwrap();
+                let inverse = flattener.inverse(e2);
+                vec![flattener.mult(e1, inverse)]
+            }
+            FieldElement::Pow(ref e, exponent) => {
+                // square and multiply
+                let mut square = e.flatten(flattener).pop().unwrap();
+                let mut result = LinComb::constant(T::one());
+                let mut exponent = exponent;
+                while exponent > 0 {
+                    if exponent & 1 == 1 {
+                        result = flattener.mult(result, square.clone());
+                    }
+                    exponent >>= 1;
+                    if exponent > 0 {
+                        square = flattener.mult(square.clone(), square);
+                    }
+                }
+                vec![result]
+            }
+            FieldElement::Member(ref s, ref id) => s.flatten_member(id, flattener),
+            FieldElement::Element(ref t, index) => t.flatten_element(index, flattener),
+            FieldElement::Select(ref a, ref i) => flatten_select(&**a, i, flattener),
+            FieldElement::IfElse(ref c, ref a, ref b) => {
+                flatten_if_else(c, &**a, &**b, flattener)
+            }
+            FieldElement::Match(ref e, ref arms) => flatten_match(e, arms, flattener),
+            FieldElement::UnwrapOr(ref o, ref d) => flatten_unwrap_or(o, &**d, flattener),
+            FieldElement::FunctionCall(ref id, ref args) => flattener.call(id, args),
         }
     }
+
+    fn get_type(&self) -> Type {
+        Type::FieldElement
+    }
 }
 
-impl fmt::Display for FieldElement {
+impl<T: Field> fmt::Display for FieldElement<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            FieldElement::Identifier(ref id) => write!(f, "{:?}", id),
+            FieldElement::Identifier(ref id) => write!(f, "{}", id),
             FieldElement::Value(ref v) => write!(f, "{}", v),
             FieldElement::Add(ref f1, ref f2) => write!(f, "{} + {}", f1, f2),
+            FieldElement::Sub(ref f1, ref f2) => write!(f, "{} - {}", f1, f2),
+            FieldElement::Mult(ref f1, ref f2) => write!(f, "{} * {}", f1, f2),
+            FieldElement::Div(ref f1, ref f2) => write!(f, "{} / {}", f1, f2),
+            FieldElement::Pow(ref f1, ref exponent) => write!(f, "{}**{}", f1, exponent),
+            FieldElement::Member(ref s, ref id) => write!(f, "{}.{}", s, id),
+            FieldElement::Element(ref t, index) => write!(f, "{}.{}", t, index),
+            FieldElement::Select(ref a, ref i) => write!(f, "{}[{}]", a, i),
+            FieldElement::IfElse(ref c, ref a, ref b) => {
+                write!(f, "if {} then {} else {}", c, a, b)
+            }
+            FieldElement::Match(ref e, ref arms) => fmt_match(f, e, arms),
+            FieldElement::UnwrapOr(ref o, ref d) => write!(f, "{}.unwrap_or({})", o, d),
             FieldElement::FunctionCall(ref id, ref args) => write!(
                 f,
                 "{}({})",
@@ -1,9 +1,17 @@
 mod array;
 mod boolean;
+mod enumeration;
 mod field_element;
+mod optional;
 mod structure;
+mod tuple;
+mod uint;
 
 pub use self::array::*;
 pub use self::boolean::*;
+pub use self::enumeration::*;
 pub use self::field_element::*;
+pub use self::optional::*;
 pub use self::structure::*;
+pub use self::tuple::*;
+pub use self::uint::*;
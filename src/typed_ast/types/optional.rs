@@ -0,0 +1,116 @@
+use field::Field;
+use flat_ast::*;
+use std::fmt;
+use typed_ast::types::{
+    flatten_if_else, flatten_match, flatten_select, fmt_match, Array, Boolean, Enumeration,
+    FieldElement, MatchArm, Structure, Tuple,
+};
+use typed_ast::{Expression, Flattener, Type, Variable};
+
+// A value which may be absent, laid out as a presence flag followed by the value, all of whose
+// slots are zero when it is absent
+#[derive(Debug)]
+pub enum Optional<T: Field> {
+    Some(Box<Expression<T>>),
+    // the type of the value which is absent
+    None(Type),
+    Identifier(Variable),
+    Member(Box<Structure<T>>, String),
+    Element(Box<Tuple<T>>, usize),
+    Select(Box<Array<T, Optional<T>>>, Box<FieldElement<T>>),
+    IfElse(Box<Boolean<T>>, Box<Optional<T>>, Box<Optional<T>>),
+    Match(Box<Enumeration<T>>, Vec<MatchArm<Optional<T>>>),
+    UnwrapOr(Box<Optional<T>>, Box<Optional<T>>),
+    FunctionCall(Type, String, Vec<Box<Expression<T>>>),
+}
+
+// `flag * (value - default) + default`, slot by slot. A constant flag picks a side without
+// flattening the other one
+pub fn flatten_unwrap_or<T: Field>(
+    optional: &Optional<T>,
+    default: &Expression<T>,
+    flattener: &mut Flattener<T>,
+) -> Vec<LinComb<T>> {
+    let mut slots = optional.flatten(flattener);
+    let value = slots.split_off(1);
+    let flag = slots.pop().unwrap();
+
+    match flag.as_constant() {
+        Some(ref f) if f.is_zero() => default.flatten(flattener),
+        Some(_) => value,
+        None => {
+            let default = default.flatten(flattener);
+            value
+                .into_iter()
+                .zip(default)
+                .map(|(v, d)| {
+                    let difference = v - d.clone();
+                    flattener.mult(flag.clone(), difference) + d
+                }).collect()
+        }
+    }
+}
+
+impl<T: Field> Expression<T> for Optional<T> {
+    fn flatten(&self, flattener: &mut Flattener<T>) -> Vec<LinComb<T>> {
+        match *self {
+            Optional::Some(ref e) => {
+                let mut slots = vec![LinComb::constant(T::one())];
+                slots.extend(e.flatten(flattener));
+                slots
+            }
+            Optional::None(ref ty) => vec![LinComb::constant(T::zero()); 1 + ty.size()],
+            Optional::Identifier(ref v) => flattener.identifier(v),
+            Optional::Member(ref s, ref id) => s.flatten_member(id, flattener),
+            Optional::Element(ref t, index) => t.flatten_element(index, flattener),
+            Optional::Select(ref a, ref i) => flatten_select(&**a, i, flattener),
+            Optional::IfElse(ref c, ref a, ref b) => flatten_if_else(c, &**a, &**b, flattener),
+            Optional::Match(ref e, ref arms) => flatten_match(e, arms, flattener),
+            Optional::UnwrapOr(ref o, ref d) => flatten_unwrap_or(o, &**d, flattener),
+            Optional::FunctionCall(_, ref id, ref args) => flattener.call(id, args),
+        }
+    }
+
+    fn get_type(&self) -> Type {
+        match *self {
+            Optional::Some(ref e) => Type::Option(box e.get_type()),
+            Optional::None(ref ty) => Type::Option(box ty.clone()),
+            Optional::Identifier(ref v) => v.get_type(),
+            Optional::Member(ref s, ref id) => s.member_type(id),
+            Optional::Element(ref t, index) => t.element_type(index),
+            Optional::Select(ref a, _) => match a.get_type() {
+                Type::Array(inner, _) => *inner,
+                ty => panic!("cannot index into {} of type {}", a, ty),
+            },
+            Optional::IfElse(_, ref a, _) => a.get_type(),
+            Optional::Match(_, ref arms) => arms[0].expression.get_type(),
+            Optional::UnwrapOr(_, ref d) => d.get_type(),
+            Optional::FunctionCall(ref ty, ..) => ty.clone(),
+        }
+    }
+}
+
+impl<T: Field> fmt::Display for Optional<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Optional::Some(ref e) => write!(f, "Some({})", e),
+            Optional::None(_) => write!(f, "None"),
+            Optional::Identifier(ref id) => write!(f, "{}", id),
+            Optional::Member(ref s, ref id) => write!(f, "{}.{}", s, id),
+            Optional::Element(ref t, index) => write!(f, "{}.{}", t, index),
+            Optional::Select(ref a, ref i) => write!(f, "{}[{}]", a, i),
+            Optional::IfElse(ref c, ref a, ref b) => write!(f, "if {} then {} else {}", c, a, b),
+            Optional::Match(ref e, ref arms) => fmt_match(f, e, arms),
+            Optional::UnwrapOr(ref o, ref d) => write!(f, "{}.unwrap_or({})", o, d),
+            Optional::FunctionCall(_, ref id, ref args) => write!(
+                f,
+                "{}({})",
+                id,
+                args.iter()
+                    .map(|e| format!("{}", e))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
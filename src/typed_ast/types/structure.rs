@@ -1,56 +1,116 @@
+use field::Field;
 use flat_ast::*;
 use std::fmt;
-use typed_ast::{Expression, Variable};
+use typed_ast::types::{
+    flatten_if_else, flatten_match, flatten_select, flatten_unwrap_or, fmt_match, Array, Boolean,
+    Enumeration, FieldElement, MatchArm, Optional, Tuple,
+};
+use typed_ast::{Expression, Flattener, Type, TypeError, Variable};
 
 #[derive(Debug)]
-pub enum Structure {
-    Value(Vec<Box<Expression>>),
+pub enum Structure<T: Field> {
+    Value(Vec<(String, Box<Expression<T>>)>),
     Identifier(Variable),
-    FunctionCall(String, Vec<Box<Expression>>),
+    Member(Box<Structure<T>>, String),
+    Element(Box<Tuple<T>>, usize),
+    Select(Box<Array<T, Structure<T>>>, Box<FieldElement<T>>),
+    IfElse(Box<Boolean<T>>, Box<Structure<T>>, Box<Structure<T>>),
+    Match(Box<Enumeration<T>>, Vec<MatchArm<Structure<T>>>),
+    UnwrapOr(Box<Optional<T>>, Box<Structure<T>>),
+    FunctionCall(Type, String, Vec<Box<Expression<T>>>),
 }
 
-impl Structure {
-    pub fn value(elements: Vec<Box<Expression>>) -> Structure {
-        assert_eq!(2, elements.len());
-        Structure::Value(elements)
+impl<T: Field> Structure<T> {
+    pub fn value(members: Vec<(String, Box<Expression<T>>)>) -> Result<Structure<T>, TypeError> {
+        for (index, (id, _)) in members.iter().enumerate() {
+            if members[..index].iter().any(|(other, _)| other == id) {
+                return Err(TypeError::new(format!("duplicate member {}", id)));
+            }
+        }
+        Ok(Structure::Value(members))
+    }
+
+    pub fn member_type(&self, id: &str) -> Type {
+        match self.get_type().member(id) {
+            Some((_, ty)) => ty.clone(),
+            None => panic!("{} has no member {}", self, id),
+        }
+    }
+
+    // a member is laid out on a contiguous run of the structure's slots, starting at its offset
+    pub fn flatten_member(&self, id: &str, flattener: &mut Flattener<T>) -> Vec<LinComb<T>> {
+        let ty = self.get_type();
+        let (offset, member) = match ty.member(id) {
+            Some(m) => m,
+            None => panic!("{} has no member {}", self, id),
+        };
+
+        let mut e = self.flatten(flattener);
+        e.drain(offset..offset + member.size()).collect()
     }
 }
 
-impl Expression for Structure {
-    fn flatten(&self, flatten_statements: &mut Vec<FlatStatement>) -> Vec<LinComb> {
+impl<T: Field> Expression<T> for Structure<T> {
+    fn flatten(&self, flattener: &mut Flattener<T>) -> Vec<LinComb<T>> {
         match *self {
-            Structure::Identifier(ref v) => vec![0, 1]
-                .iter()
-                .map(|n| {
-                    LinComb(vec![(
-                        1,
-                        FlatVariable::with_name(format!("{}_{}", v.name(), n)),
-                    )])
-                }).collect(),
+            Structure::Identifier(ref v) => flattener.identifier(v),
             Structure::Value(ref v) => v
                 .iter()
-                .map(|v| v.flatten(flatten_statements))
+                .map(|(_, v)| v.flatten(flattener))
                 .flat_map(|x| x)
                 .collect(),
-            _ => unimplemented!(),
+            Structure::Member(ref s, ref id) => s.flatten_member(id, flattener),
+            Structure::Element(ref t, index) => t.flatten_element(index, flattener),
+            Structure::Select(ref a, ref i) => flatten_select(&**a, i, flattener),
+            Structure::IfElse(ref c, ref a, ref b) => flatten_if_else(c, &**a, &**b, flattener),
+            Structure::Match(ref e, ref arms) => flatten_match(e, arms, flattener),
+            Structure::UnwrapOr(ref o, ref d) => flatten_unwrap_or(o, &**d, flattener),
+            Structure::FunctionCall(_, ref id, ref args) => flattener.call(id, args),
+        }
+    }
+
+    fn get_type(&self) -> Type {
+        match *self {
+            Structure::Value(ref v) => Type::Struct(
+                v.iter()
+                    .map(|(id, e)| (id.clone(), e.get_type()))
+                    .collect(),
+            ),
+            Structure::Identifier(ref v) => v.get_type(),
+            Structure::Member(ref s, ref id) => s.member_type(id),
+            Structure::Element(ref t, index) => t.element_type(index),
+            Structure::Select(ref a, _) => match a.get_type() {
+                Type::Array(inner, _) => *inner,
+                ty => panic!("cannot index into {} of type {}", a, ty),
+            },
+            Structure::IfElse(_, ref a, _) => a.get_type(),
+            Structure::Match(_, ref arms) => arms[0].expression.get_type(),
+            Structure::UnwrapOr(_, ref d) => d.get_type(),
+            Structure::FunctionCall(ref ty, ..) => ty.clone(),
         }
     }
 }
 
-impl fmt::Display for Structure {
+impl<T: Field> fmt::Display for Structure<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Structure::Identifier(ref id) => write!(f, "{:?}", id),
+            Structure::Identifier(ref id) => write!(f, "{}", id),
             Structure::Value(ref values) => write!(
                 f,
-                "[{}]",
+                "{{{}}}",
                 values
                     .iter()
-                    .map(|e| format!("{}", e))
+                    .map(|(id, e)| format!("{}: {}", id, e))
                     .collect::<Vec<_>>()
                     .join(", ")
             ),
-            Structure::FunctionCall(ref id, ref args) => write!(
+            Structure::Member(ref s, ref id) => write!(f, "{}.{}", s, id),
+            Structure::Element(ref t, index) => write!(f, "{}.{}", t, index),
+            Structure::Select(ref a, ref i) => write!(f, "{}[{}]", a, i),
+            Structure::IfElse(ref c, ref a, ref b) => write!(f, "if {} then {} else {}", c, a, b),
+            Structure::Match(ref e, ref arms) => fmt_match(f, e, arms),
+            Structure::UnwrapOr(ref o, ref d) => write!(f, "{}.unwrap_or({})", o, d),
+            Structure::FunctionCall(_, ref id, ref args) => write!(
                 f,
                 "{}({})",
                 id,
@@ -0,0 +1,120 @@
+use field::Field;
+use flat_ast::*;
+use std::fmt;
+use typed_ast::types::{
+    flatten_if_else, flatten_match, flatten_select, flatten_unwrap_or, fmt_match, Array, Boolean,
+    Enumeration, FieldElement, MatchArm, Optional, Structure,
+};
+use typed_ast::{Expression, Flattener, Type, TypeError, Variable};
+
+// A fixed number of values of possibly different types, laid out one after the other like the
+// members of a structure
+#[derive(Debug)]
+pub enum Tuple<T: Field> {
+    Value(Vec<Box<Expression<T>>>),
+    Identifier(Variable),
+    Member(Box<Structure<T>>, String),
+    Element(Box<Tuple<T>>, usize),
+    Select(Box<Array<T, Tuple<T>>>, Box<FieldElement<T>>),
+    IfElse(Box<Boolean<T>>, Box<Tuple<T>>, Box<Tuple<T>>),
+    Match(Box<Enumeration<T>>, Vec<MatchArm<Tuple<T>>>),
+    UnwrapOr(Box<Optional<T>>, Box<Tuple<T>>),
+    // a call to a function returning several values
+    FunctionCall(Type, String, Vec<Box<Expression<T>>>),
+}
+
+impl<T: Field> Tuple<T> {
+    pub fn value(elements: Vec<Box<Expression<T>>>) -> Result<Tuple<T>, TypeError> {
+        if elements.is_empty() {
+            return Err(TypeError::new("empty tuples are not supported"));
+        }
+        Ok(Tuple::Value(elements))
+    }
+
+    pub fn element_type(&self, index: usize) -> Type {
+        match self.get_type().element(index) {
+            Some((_, ty)) => ty.clone(),
+            None => panic!("{} has no element {}", self, index),
+        }
+    }
+
+    // an element is laid out on a contiguous run of the tuple's slots, starting at its offset
+    pub fn flatten_element(&self, index: usize, flattener: &mut Flattener<T>) -> Vec<LinComb<T>> {
+        let ty = self.get_type();
+        let (offset, element) = match ty.element(index) {
+            Some(e) => e,
+            None => panic!("{} has no element {}", self, index),
+        };
+
+        let mut e = self.flatten(flattener);
+        e.drain(offset..offset + element.size()).collect()
+    }
+}
+
+impl<T: Field> Expression<T> for Tuple<T> {
+    fn flatten(&self, flattener: &mut Flattener<T>) -> Vec<LinComb<T>> {
+        match *self {
+            Tuple::Identifier(ref v) => flattener.identifier(v),
+            Tuple::Value(ref v) => v.iter().flat_map(|e| e.flatten(flattener)).collect(),
+            Tuple::Member(ref s, ref id) => s.flatten_member(id, flattener),
+            Tuple::Element(ref t, index) => t.flatten_element(index, flattener),
+            Tuple::Select(ref a, ref i) => flatten_select(&**a, i, flattener),
+            Tuple::IfElse(ref c, ref a, ref b) => flatten_if_else(c, &**a, &**b, flattener),
+            Tuple::Match(ref e, ref arms) => flatten_match(e, arms, flattener),
+            Tuple::UnwrapOr(ref o, ref d) => flatten_unwrap_or(o, &**d, flattener),
+            Tuple::FunctionCall(_, ref id, ref args) => flattener.call(id, args),
+        }
+    }
+
+    fn get_type(&self) -> Type {
+        match *self {
+            Tuple::Value(ref v) => Type::Tuple(v.iter().map(|e| e.get_type()).collect()),
+            Tuple::Identifier(ref v) => v.get_type(),
+            Tuple::Member(ref s, ref id) => s.member_type(id),
+            Tuple::Element(ref t, index) => t.element_type(index),
+            Tuple::Select(ref a, _) => match a.get_type() {
+                Type::Array(inner, _) => *inner,
+                ty => panic!("cannot index into {} of type {}", a, ty),
+            },
+            Tuple::IfElse(_, ref a, _) => a.get_type(),
+            Tuple::Match(_, ref arms) => arms[0].expression.get_type(),
+            Tuple::UnwrapOr(_, ref d) => d.get_type(),
+            Tuple::FunctionCall(ref ty, ..) => ty.clone(),
+        }
+    }
+}
+
+impl<T: Field> fmt::Display for Tuple<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Tuple::Identifier(ref id) => write!(f, "{}", id),
+            Tuple::Value(ref values) => match values.len() {
+                1 => write!(f, "({},)", values[0]),
+                _ => write!(
+                    f,
+                    "({})",
+                    values
+                        .iter()
+                        .map(|e| format!("{}", e))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            },
+            Tuple::Member(ref s, ref id) => write!(f, "{}.{}", s, id),
+            Tuple::Element(ref t, index) => write!(f, "{}.{}", t, index),
+            Tuple::Select(ref a, ref i) => write!(f, "{}[{}]", a, i),
+            Tuple::IfElse(ref c, ref a, ref b) => write!(f, "if {} then {} else {}", c, a, b),
+            Tuple::Match(ref e, ref arms) => fmt_match(f, e, arms),
+            Tuple::UnwrapOr(ref o, ref d) => write!(f, "{}.unwrap_or({})", o, d),
+            Tuple::FunctionCall(_, ref id, ref args) => write!(
+                f,
+                "{}({})",
+                id,
+                args.iter()
+                    .map(|e| format!("{}", e))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use field::Field;
+use flat_ast::*;
+use num_bigint::BigUint;
+use std::fmt;
+use typed_ast::types::{
+    flatten_if_else, flatten_match, flatten_select, flatten_unwrap_or, fmt_match, Array, Boolean,
+    Enumeration, FieldElement, MatchArm, Optional, Structure, Tuple,
+};
+use typed_ast::{Expression, Flattener, Type, Variable};
+
+// An unsigned integer, packed in a single field element whose range is checked whenever it is
+// decomposed into bits. Arithmetic wraps around
+#[derive(Debug)]
+pub enum Uint<T: Field> {
+    Identifier(Variable),
+    // the value and the bit width
+    Value(u64, usize),
+    Add(Box<Uint<T>>, Box<Uint<T>>),
+    Sub(Box<Uint<T>>, Box<Uint<T>>),
+    Mult(Box<Uint<T>>, Box<Uint<T>>),
+    And(Box<Uint<T>>, Box<Uint<T>>),
+    Or(Box<Uint<T>>, Box<Uint<T>>),
+    Xor(Box<Uint<T>>, Box<Uint<T>>),
+    Not(Box<Uint<T>>),
+    LeftShift(Box<Uint<T>>, usize),
+    RightShift(Box<Uint<T>>, usize),
+    Member(Box<Structure<T>>, String),
+    Element(Box<Tuple<T>>, usize),
+    Select(Box<Array<T, Uint<T>>>, Box<FieldElement<T>>),
+    IfElse(Box<Boolean<T>>, Box<Uint<T>>, Box<Uint<T>>),
+    Match(Box<Enumeration<T>>, Vec<MatchArm<Uint<T>>>),
+    UnwrapOr(Box<Optional<T>>, Box<Uint<T>>),
+    FunctionCall(Type, String, Vec<Box<Expression<T>>>),
+}
+
+impl<T: Field> Uint<T> {
+    pub fn bitwidth(&self) -> usize {
+        match self.get_type() {
+            Type::Uint(bitwidth) => bitwidth,
+            ty => panic!("{} is of type {}, not an integer", self, ty),
+        }
+    }
+}
+
+// the `bitwidth` low bits of `a`, which is known to fit in `count` bits
+fn truncate<T: Field>(
+    a: LinComb<T>,
+    count: usize,
+    bitwidth: usize,
+    flattener: &mut Flattener<T>,
+) -> LinComb<T> {
+    let bits = flattener.bits(a, count);
+    Flattener::pack(&bits[..bitwidth])
+}
+
+// `f` applied to each pair of bits of `a` and `b`
+fn bitwise<T: Field, F>(
+    a: &Uint<T>,
+    b: &Uint<T>,
+    flattener: &mut Flattener<T>,
+    f: F,
+) -> LinComb<T>
+where
+    F: Fn(LinComb<T>, LinComb<T>, &mut Flattener<T>) -> LinComb<T>,
+{
+    let bitwidth = a.bitwidth();
+    let a = a.flatten(flattener).pop().unwrap();
+    let b = b.flatten(flattener).pop().unwrap();
+    let a = flattener.bits(a, bitwidth);
+    let b = flattener.bits(b, bitwidth);
+    let bits: Vec<_> = a
+        .into_iter()
+        .zip(b)
+        .map(|(a, b)| f(a, b, flattener))
+        .collect();
+    Flattener::pack(&bits)
+}
+
+impl<T: Field> Expression<T> for Uint<T> {
+    fn flatten(&self, flattener: &mut Flattener<T>) -> Vec<LinComb<T>> {
+        match *self {
+            Uint::Identifier(ref v) => flattener.identifier(v),
+            Uint::Value(v, _) => {
+                vec![LinComb::constant(T::try_from_biguint(BigUint::from(v)).unwrap())]
+            }
+            Uint::Add(ref e1, ref e2) => {
+                // the sum takes one more bit, which is dropped
+                let bitwidth = self.bitwidth();
+                let e1 = e1.flatten(flattener).pop().unwrap();
+                let e2 = e2.flatten(flattener).pop().unwrap();
+                vec![truncate(e1 + e2, bitwidth + 1, bitwidth, flattener)]
+            }
+            Uint::Sub(ref e1, ref e2) => {
+                // 2^n + a - b is positive and takes one more bit, which is dropped
+                let bitwidth = self.bitwidth();
+                let e1 = e1.flatten(flattener).pop().unwrap();
+                let e2 = e2.flatten(flattener).pop().unwrap();
+                let shifted = LinComb::constant(T::from(2).pow(bitwidth)) + e1 - e2;
+                vec![truncate(shifted, bitwidth + 1, bitwidth, flattener)]
+            }
+            Uint::Mult(ref e1, ref e2) => {
+                // the product takes twice the bits, the high half is dropped
+                let bitwidth = self.bitwidth();
+                let e1 = e1.flatten(flattener).pop().unwrap();
+                let e2 = e2.flatten(flattener).pop().unwrap();
+                let product = flattener.mult(e1, e2);
+                vec![truncate(product, 2 * bitwidth, bitwidth, flattener)]
+            }
+            Uint::And(ref e1, ref e2) => vec![bitwise(e1, e2, flattener, |a, b, flattener| {
+                flattener.mult(a, b)
+            })],
+            Uint::Or(ref e1, ref e2) => vec![bitwise(e1, e2, flattener, |a, b, flattener| {
+                // a + b - a * b
+                let product = flattener.mult(a.clone(), b.clone());
+                a + b - product
+            })],
+            Uint::Xor(ref e1, ref e2) => vec![bitwise(e1, e2, flattener, |a, b, flattener| {
+                // a + b - 2 * a * b
+                let product = flattener.mult(a.clone(), b.clone());
+                a + b - product.scale(T::from(2))
+            })],
+            Uint::Not(ref e) => {
+                // flipping every bit is subtracting from 2^n - 1
+                let bitwidth = self.bitwidth();
+                let e = e.flatten(flattener).pop().unwrap();
+                vec![LinComb::constant(T::from(2).pow(bitwidth) - T::one()) - e]
+            }
+            Uint::LeftShift(ref e, by) => {
+                let bitwidth = self.bitwidth();
+                let e = e.flatten(flattener).pop().unwrap();
+                let bits = flattener.bits(e, bitwidth);
+                let kept = bitwidth.saturating_sub(by);
+                vec![Flattener::pack(&bits[..kept]).scale(T::from(2).pow(by))]
+            }
+            Uint::RightShift(ref e, by) => {
+                let bitwidth = self.bitwidth();
+                let e = e.flatten(flattener).pop().unwrap();
+                let bits = flattener.bits(e, bitwidth);
+                vec![Flattener::pack(&bits[by.min(bitwidth)..])]
+            }
+            Uint::Member(ref s, ref id) => s.flatten_member(id, flattener),
+            Uint::Element(ref t, index) => t.flatten_element(index, flattener),
+            Uint::Select(ref a, ref i) => flatten_select(&**a, i, flattener),
+            Uint::IfElse(ref c, ref a, ref b) => flatten_if_else(c, &**a, &**b, flattener),
+            Uint::Match(ref e, ref arms) => flatten_match(e, arms, flattener),
+            Uint::UnwrapOr(ref o, ref d) => flatten_unwrap_or(o, &**d, flattener),
+            Uint::FunctionCall(_, ref id, ref args) => flattener.call(id, args),
+        }
+    }
+
+    fn get_type(&self) -> Type {
+        match *self {
+            Uint::Identifier(ref v) => v.get_type(),
+            Uint::Value(_, bitwidth) => Type::Uint(bitwidth),
+            Uint::Add(ref e, _)
+            | Uint::Sub(ref e, _)
+            | Uint::Mult(ref e, _)
+            | Uint::And(ref e, _)
+            | Uint::Or(ref e, _)
+            | Uint::Xor(ref e, _)
+            | Uint::Not(ref e)
+            | Uint::LeftShift(ref e, _)
+            | Uint::RightShift(ref e, _) => e.get_type(),
+            Uint::Member(ref s, ref id) => s.member_type(id),
+            Uint::Element(ref t, index) => t.element_type(index),
+            Uint::Select(ref a, _) => match a.get_type() {
+                Type::Array(inner, _) => *inner,
+                ty => panic!("cannot index into {} of type {}", a, ty),
+            },
+            Uint::IfElse(_, ref a, _) => a.get_type(),
+            Uint::Match(_, ref arms) => arms[0].expression.get_type(),
+            Uint::UnwrapOr(_, ref d) => d.get_type(),
+            Uint::FunctionCall(ref ty, ..) => ty.clone(),
+        }
+    }
+}
+
+impl<T: Field> fmt::Display for Uint<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Uint::Identifier(ref id) => write!(f, "{}", id),
+            Uint::Value(v, bitwidth) => write!(f, "{}_u{}", v, bitwidth),
+            Uint::Add(ref e1, ref e2) => write!(f, "{} + {}", e1, e2),
+            Uint::Sub(ref e1, ref e2) => write!(f, "{} - {}", e1, e2),
+            Uint::Mult(ref e1, ref e2) => write!(f, "{} * {}", e1, e2),
+            Uint::And(ref e1, ref e2) => write!(f, "{} ^ {}", e1, e2),
+            Uint::Or(ref e1, ref e2) => write!(f, "{} v {}", e1, e2),
+            Uint::Xor(ref e1, ref e2) => write!(f, "{} xor {}", e1, e2),
+            Uint::Not(ref e) => write!(f, "!{}", e),
+            Uint::LeftShift(ref e, by) => write!(f, "{} << {}", e, by),
+            Uint::RightShift(ref e, by) => write!(f, "{} >> {}", e, by),
+            Uint::Member(ref s, ref id) => write!(f, "{}.{}", s, id),
+            Uint::Element(ref t, index) => write!(f, "{}.{}", t, index),
+            Uint::Select(ref a, ref i) => write!(f, "{}[{}]", a, i),
+            Uint::IfElse(ref c, ref a, ref b) => write!(f, "if {} then {} else {}", c, a, b),
+            Uint::Match(ref e, ref arms) => fmt_match(f, e, arms),
+            Uint::UnwrapOr(ref o, ref d) => write!(f, "{}.unwrap_or({})", o, d),
+            Uint::FunctionCall(_, ref id, ref args) => write!(
+                f,
+                "{}({})",
+                id,
+                args.iter()
+                    .map(|e| format!("{}", e))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}